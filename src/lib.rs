@@ -1,63 +1,164 @@
-#![feature(clone_from_slice)]
-#![feature(zero_one)]
+// Lints tripped by the existing public API and by the aligned struct-field
+// style used throughout the crate.
+#![allow(clippy::len_without_is_empty)]
+#![allow(clippy::missing_safety_doc)]
+#![allow(clippy::ok_expect)]
+#![allow(clippy::redundant_field_names)]
+#![allow(clippy::result_unit_err)]
 
 extern crate byteorder;
+#[cfg(feature = "rand")]
+extern crate rand;
 
-use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+pub mod npy;
+#[cfg(test)]
+mod tests;
 
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+#[cfg(feature = "rand")]
+use rand::{Rng};
+
+use std::alloc::{Layout, alloc, dealloc};
+use std::cmp::{max, min};
+use std::convert::{TryFrom};
+use std::f32;
+use std::fmt::{Debug};
+use std::hash::{Hash, Hasher};
 use std::io::{Read, Write};
-use std::mem::{size_of};
-use std::num::{Zero};
-use std::slice::{from_raw_parts, from_raw_parts_mut};
+use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+use std::mem::{MaybeUninit, align_of, forget, size_of, size_of_val, zeroed};
+use std::ops::{Add, Div, Mul, Sub};
+use std::ptr::{copy_nonoverlapping, write_bytes};
+use std::slice::{ChunksMut, from_raw_parts, from_raw_parts_mut};
+use std::vec::{IntoIter};
+
+#[macro_export]
+macro_rules! assert_array_eq {
+  ($a:expr, $b:expr) => {
+    match $crate::ArrayMismatch::first_mismatch(&$a, &$b) {
+      Some(msg) => panic!("assertion failed: arrays differ: {}", msg),
+      None => {}
+    }
+  };
+}
 
 pub trait Shape: Copy {
-  type Stride: Copy;
+  type Stride: Copy + PartialEq;
 
   fn to_least_stride(&self) -> Self::Stride;
+  fn ndim(&self) -> usize;
+  fn dim(&self, axis: usize) -> usize;
   fn len(&self) -> usize;
   fn offset(&self, stride: Self::Stride) -> usize;
-
-  fn major_iter(self) -> MajorIter<Self> where Self: Default {
+  // Inverse of `offset`: the index whose offset under `stride` is `offset`.
+  fn coord_from_offset(offset: usize, stride: Self::Stride) -> Self;
+  fn checked_offset(&self, bound: Self, stride: Self::Stride) -> Option<usize>;
+  fn to_vec(&self) -> Vec<usize>;
+  fn from_vec(v: &[usize]) -> Option<Self>;
+  fn zero_index() -> Self;
+
+  // Yields indices in increasing offset order for a contiguous array, i.e.
+  // with `.0` varying fastest.
+  fn major_iter(self) -> MajorIter<Self> {
     MajorIter{
-      idx:          Default::default(),
+      idx:          Self::zero_index(),
       upper_bound:  self,
+      done:         self.len() == 0,
     }
   }
+
+  fn offset_order_iter(self) -> MajorIter<Self> {
+    self.major_iter()
+  }
 }
 
 pub struct MajorIter<S> where S: Shape {
   idx:          S,
   upper_bound:  S,
+  done:         bool,
+}
+
+impl Iterator for MajorIter<usize> {
+  type Item = usize;
+
+  fn next(&mut self) -> Option<usize> {
+    if self.done {
+      return None;
+    }
+    let idx = self.idx;
+    self.idx += 1;
+    if self.idx < self.upper_bound {
+      return Some(idx);
+    }
+    self.done = true;
+    Some(idx)
+  }
+}
+
+impl Iterator for MajorIter<(usize, usize)> {
+  type Item = (usize, usize);
+
+  fn next(&mut self) -> Option<(usize, usize)> {
+    if self.done {
+      return None;
+    }
+    let idx = self.idx;
+    self.idx.0 += 1;
+    if self.idx.0 < self.upper_bound.0 {
+      return Some(idx);
+    }
+    self.idx.0 = 0;
+    self.idx.1 += 1;
+    if self.idx.1 < self.upper_bound.1 {
+      return Some(idx);
+    }
+    self.done = true;
+    Some(idx)
+  }
 }
 
 impl Iterator for MajorIter<(usize, usize, usize)> {
   type Item = (usize, usize, usize);
 
   fn next(&mut self) -> Option<(usize, usize, usize)> {
-    // FIXME(20160203): this only terminates "once".
+    if self.done {
+      return None;
+    }
+    let idx = self.idx;
     self.idx.0 += 1;
     if self.idx.0 < self.upper_bound.0 {
-      return Some(self.idx);
+      return Some(idx);
     }
     self.idx.0 = 0;
     self.idx.1 += 1;
     if self.idx.1 < self.upper_bound.1 {
-      return Some(self.idx);
+      return Some(idx);
     }
     self.idx.1 = 0;
     self.idx.2 += 1;
     if self.idx.2 < self.upper_bound.2 {
-      return Some(self.idx);
+      return Some(idx);
     }
-    None
+    self.done = true;
+    Some(idx)
   }
 }
 
 impl Shape for usize {
   type Stride = ();
 
-  fn to_least_stride(&self) -> () {
-    ()
+  fn to_least_stride(&self) {
+  }
+
+  fn ndim(&self) -> usize {
+    1
+  }
+
+  fn dim(&self, axis: usize) -> usize {
+    match axis {
+      0 => *self,
+      _ => panic!("axis out of range: {}", axis),
+    }
   }
 
   fn len(&self) -> usize {
@@ -67,6 +168,33 @@ impl Shape for usize {
   fn offset(&self, _: ()) -> usize {
     *self
   }
+
+  fn coord_from_offset(offset: usize, _: ()) -> usize {
+    offset
+  }
+
+  fn checked_offset(&self, bound: usize, stride: ()) -> Option<usize> {
+    if *self < bound {
+      Some(self.offset(stride))
+    } else {
+      None
+    }
+  }
+
+  fn to_vec(&self) -> Vec<usize> {
+    vec![*self]
+  }
+
+  fn from_vec(v: &[usize]) -> Option<usize> {
+    match v.len() {
+      1 => Some(v[0]),
+      _ => None,
+    }
+  }
+
+  fn zero_index() -> usize {
+    0
+  }
 }
 
 impl Shape for (usize, usize) {
@@ -76,6 +204,18 @@ impl Shape for (usize, usize) {
     self.0
   }
 
+  fn ndim(&self) -> usize {
+    2
+  }
+
+  fn dim(&self, axis: usize) -> usize {
+    match axis {
+      0 => self.0,
+      1 => self.1,
+      _ => panic!("axis out of range: {}", axis),
+    }
+  }
+
   fn len(&self) -> usize {
     self.0 * self.1
   }
@@ -83,6 +223,33 @@ impl Shape for (usize, usize) {
   fn offset(&self, stride: usize) -> usize {
     self.0 + self.1 * stride
   }
+
+  fn coord_from_offset(offset: usize, stride: usize) -> (usize, usize) {
+    (offset % stride, offset / stride)
+  }
+
+  fn checked_offset(&self, bound: (usize, usize), stride: usize) -> Option<usize> {
+    if self.0 < bound.0 && self.1 < bound.1 {
+      Some(self.offset(stride))
+    } else {
+      None
+    }
+  }
+
+  fn to_vec(&self) -> Vec<usize> {
+    vec![self.0, self.1]
+  }
+
+  fn from_vec(v: &[usize]) -> Option<(usize, usize)> {
+    match v.len() {
+      2 => Some((v[0], v[1])),
+      _ => None,
+    }
+  }
+
+  fn zero_index() -> (usize, usize) {
+    (0, 0)
+  }
 }
 
 impl Shape for (usize, usize, usize) {
@@ -92,6 +259,19 @@ impl Shape for (usize, usize, usize) {
     (self.0, self.1)
   }
 
+  fn ndim(&self) -> usize {
+    3
+  }
+
+  fn dim(&self, axis: usize) -> usize {
+    match axis {
+      0 => self.0,
+      1 => self.1,
+      2 => self.2,
+      _ => panic!("axis out of range: {}", axis),
+    }
+  }
+
   fn len(&self) -> usize {
     self.0 * self.1 * self.2
   }
@@ -99,20 +279,113 @@ impl Shape for (usize, usize, usize) {
   fn offset(&self, stride: (usize, usize)) -> usize {
     self.0 + self.1 * stride.0 + self.2 * stride.1 * stride.0
   }
+
+  fn coord_from_offset(offset: usize, stride: (usize, usize)) -> (usize, usize, usize) {
+    (offset % stride.0, (offset / stride.0) % stride.1, offset / (stride.0 * stride.1))
+  }
+
+  fn checked_offset(&self, bound: (usize, usize, usize), stride: (usize, usize)) -> Option<usize> {
+    if self.0 < bound.0 && self.1 < bound.1 && self.2 < bound.2 {
+      Some(self.offset(stride))
+    } else {
+      None
+    }
+  }
+
+  fn to_vec(&self) -> Vec<usize> {
+    vec![self.0, self.1, self.2]
+  }
+
+  fn from_vec(v: &[usize]) -> Option<(usize, usize, usize)> {
+    match v.len() {
+      3 => Some((v[0], v[1], v[2])),
+      _ => None,
+    }
+  }
+
+  fn zero_index() -> (usize, usize, usize) {
+    (0, 0, 0)
+  }
 }
 
 pub trait SerialDataType: Copy {
   fn serial_id() -> u8;
+  fn read_le(reader: &mut dyn Read) -> IoResult<Self>;
+  fn write_le(self, writer: &mut dyn Write) -> IoResult<()>;
 }
 
 impl SerialDataType for u8 {
   fn serial_id() -> u8 { 0 }
+
+  fn read_le(reader: &mut dyn Read) -> IoResult<u8> {
+    reader.read_u8()
+  }
+
+  fn write_le(self, writer: &mut dyn Write) -> IoResult<()> {
+    writer.write_u8(self)
+  }
 }
 
 impl SerialDataType for f32 {
   fn serial_id() -> u8 { 1 }
+
+  fn read_le(reader: &mut dyn Read) -> IoResult<f32> {
+    reader.read_f32::<LittleEndian>()
+  }
+
+  fn write_le(self, writer: &mut dyn Write) -> IoResult<()> {
+    writer.write_f32::<LittleEndian>(self)
+  }
+}
+
+// Additive and multiplicative identities, in place of the unstable
+// `std::num::{Zero, One}` that were removed from std.
+pub trait Zero {
+  fn zero() -> Self;
+}
+
+pub trait One {
+  fn one() -> Self;
+}
+
+macro_rules! impl_zero_one {
+  ($zero:expr, $one:expr; $($t:ty),*) => {
+    $(
+      impl Zero for $t {
+        fn zero() -> $t { $zero }
+      }
+
+      impl One for $t {
+        fn one() -> $t { $one }
+      }
+    )*
+  };
+}
+
+impl_zero_one!(0, 1; i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+impl_zero_one!(0.0, 1.0; f32, f64);
+
+mod sealed {
+  pub trait Sealed {}
+}
+
+// Element types that support the generic arithmetic on arrays. Sealed, so
+// the set of implementors is exactly the primitive numeric types below.
+pub trait Numeric: sealed::Sealed + Zero + One + Copy + PartialOrd
+    + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> + Div<Output=Self> {
+}
+
+macro_rules! impl_numeric {
+  ($($t:ty),*) => {
+    $(
+      impl sealed::Sealed for $t {}
+      impl Numeric for $t {}
+    )*
+  };
 }
 
+impl_numeric!(f32, f64, i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
 pub trait Array<'a, T, S> where T: 'a + Copy, S: Shape {
   type View: ArrayView<'a, T, S>;
   type ViewMut: ArrayViewMut<'a, T, S>;
@@ -133,463 +406,3039 @@ pub trait AsyncArray<'ctx, 'a, T, S> where 'ctx: 'a, T: 'a + Copy, S: Shape {
 pub trait ArrayView<'a, T, S> where T: 'a + Copy, S: Shape {
   fn bound(&self) -> S;
   fn stride(&self) -> S::Stride;
-  fn len(&self) -> usize;
   unsafe fn as_ptr(&self) -> *const T;
   fn view(self, lo: S, hi: S) -> Self;
+
+  fn len(&self) -> usize {
+    self.bound().len()
+  }
+
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  fn is_contiguous(&self) -> bool {
+    self.stride() == self.bound().to_least_stride()
+  }
 }
 
 pub trait ArrayViewMut<'a, T, S>/*: ArrayView<'a, T, S>*/ where T: 'a + Copy, S: Shape {
   fn bound(&self) -> S;
   fn stride(&self) -> S::Stride;
-  fn len(&self) -> usize;
   unsafe fn as_ptr(&self) -> *const T;
   unsafe fn as_mut_ptr(&mut self) -> *mut T;
   fn view_mut(self, lo: S, hi: S) -> Self;
+
+  fn len(&self) -> usize {
+    self.bound().len()
+  }
+
+  fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  fn is_contiguous(&self) -> bool {
+    self.stride() == self.bound().to_least_stride()
+  }
 }
 
 pub trait ArrayZeroExt<T, S> where T: Copy, S: Shape {
   fn zeros(bound: S) -> Self;
 }
 
-pub trait NdArraySerialize<T, S> where T: SerialDataType + Copy, S: Shape {
-  fn serial_size(bound: S) -> usize;
-  fn deserialize(reader: &mut Read) -> Result<Self, ()> where Self: Sized;
-  fn serialize(&self, writer: &mut Write) -> Result<(), ()>;
+#[derive(Debug)]
+pub enum ArrayIoError {
+  Io(IoError),
+  // The reader was already at EOF before the first header byte.
+  Empty,
+  // The payload ended early; counts are in bytes.
+  Truncated{expected: usize, got: usize},
+  BadMagic,
+  BadVersion,
+  BadHeader,
+  DataTypeMismatch,
+  NdimMismatch,
+  TooLarge,
 }
 
-pub struct Array2d<T> where T: Copy {
-  data:     Vec<T>,
-  bound:    (usize, usize),
-  stride:   usize,
+#[derive(Debug)]
+pub enum CastError {
+  NotContiguous,
+  SizeMismatch,
+  Misaligned,
 }
 
-impl<T> Array2d<T> where T: Copy {
-  pub unsafe fn new(bound: (usize, usize)) -> Array2d<T> {
-    let len = bound.len();
-    let mut data = Vec::with_capacity(len);
-    data.set_len(len);
-    Array2d{
-      data:     data,
-      bound:    bound,
-      stride:   bound.to_least_stride(),
-    }
-  }
+#[derive(Debug)]
+pub enum ReshapeError {
+  NotContiguous,
+  LengthMismatch,
+}
 
-  pub fn as_slice(&self) -> &[T] {
-    &self.data
-  }
+#[derive(Debug)]
+pub enum ShapeError {
+  // A stride component is smaller than the bound component it steps over.
+  StrideTooSmall,
+  // The backing buffer is shorter than the extent addressed by the bound
+  // and stride; counts are in elements.
+  BufferTooShort{required: usize, len: usize},
+}
 
-  pub fn as_mut_slice(&mut self) -> &mut [T] {
-    &mut self.data
+// Why `try_view_mut` rejected a region. A strided (non-contiguous) parent is
+// never an error: `view_mut` slices through the parent's stride, so every
+// in-range region of any view is itself a valid view.
+#[derive(Debug)]
+pub enum ViewError<S> where S: Shape {
+  HiExceedsBound{hi: S, bound: S},
+  LoAfterHi{lo: S, hi: S},
+}
+
+impl From<IoError> for ArrayIoError {
+  fn from(e: IoError) -> ArrayIoError {
+    ArrayIoError::Io(e)
   }
 }
 
-impl<T> ArrayZeroExt<T, (usize, usize)> for Array2d<T> where T: Zero + Copy {
-  fn zeros(bound: (usize, usize)) -> Array2d<T> {
-    let len = bound.len();
-    let mut data = Vec::with_capacity(len);
-    unsafe { data.set_len(len) };
-    for i in 0 .. len {
-      data[i] = T::zero();
+// The single bounds check behind both the panicking `view`/`view_mut` and
+// the `Result`-returning `try_view`/`try_view_mut`.
+fn check_view_range<S>(lo: S, hi: S, bound: S) -> Result<(), ViewError<S>> where S: Shape {
+  for d in 0 .. bound.ndim() {
+    if hi.dim(d) > bound.dim(d) {
+      return Err(ViewError::HiExceedsBound{hi: hi, bound: bound});
     }
-    Array2d{
-      data:     data,
-      bound:    bound,
-      stride:   bound.to_least_stride(),
+  }
+  for d in 0 .. bound.ndim() {
+    if lo.dim(d) > hi.dim(d) {
+      return Err(ViewError::LoAfterHi{lo: lo, hi: hi});
     }
   }
+  Ok(())
 }
 
-impl<T> NdArraySerialize<T, (usize, usize)> for Array2d<T> where T: SerialDataType + Copy {
-  fn serial_size(bound: (usize, usize)) -> usize {
-    24 + bound.len()
-  }
-
-  fn deserialize(reader: &mut Read) -> Result<Array2d<T>, ()> {
-    let magic0 = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    let magic1 = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    assert_eq!(magic0, b'N');
-    assert_eq!(magic1, b'D');
-    let version = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    assert_eq!(version, 0);
-    let data_ty = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    let ndim = reader.read_u32::<LittleEndian>()
-      .ok().expect("failed to deserialize!");
-    assert_eq!(data_ty, T::serial_id());
-    assert_eq!(ndim, 2);
-    let bound0 = reader.read_u64::<LittleEndian>()
-      .ok().expect("failed to deserialize!") as usize;
-    let bound1 = reader.read_u64::<LittleEndian>()
-      .ok().expect("failed to deserialize!") as usize;
-    let dims = (bound0, bound1);
-    let mut arr = unsafe { Array2d::new(dims) };
-    {
-      let mut data_bytes = unsafe { from_raw_parts_mut(arr.data.as_mut_ptr() as *mut u8, size_of::<f32>() * arr.data.len()) };
-      let mut read_idx: usize = 0;
-      loop {
-        match reader.read(&mut data_bytes[read_idx ..]) {
-          Ok(n) => {
-            read_idx += n;
-            if n == 0 {
-              break;
-            }
-          }
-          Err(e) => panic!("failed to deserialize: {:?}", e),
-        }
-      }
-      assert_eq!(read_idx, data_bytes.len());
-    }
-    Ok(arr)
+fn assert_view_range<S>(lo: S, hi: S, bound: S) where S: Shape + Debug {
+  if let Err(e) = check_view_range(lo, hi, bound) {
+    panic!("invalid view range: {:?}", e);
   }
+}
 
-  fn serialize(&self, writer: &mut Write) -> Result<(), ()> {
-    let ty_id = T::serial_id();
-    writer.write_u32::<LittleEndian>(0x0000444e | ((ty_id as u32) << 24))
-      .ok().expect("failed to serialize!");
-    writer.write_u32::<LittleEndian>(2)
-      .ok().expect("failed to serialize!");
-    let (bound0, bound1) = self.bound;
-    writer.write_u64::<LittleEndian>(bound0 as u64)
-      .ok().expect("failed to serialize!");
-    writer.write_u64::<LittleEndian>(bound1 as u64)
-      .ok().expect("failed to serialize!");
-    if self.bound.to_least_stride() == self.stride {
-      let bytes = unsafe { from_raw_parts(self.data.as_ptr() as *const u8, size_of::<f32>() * self.data.len()) };
-      writer.write_all(bytes)
-        .ok().expect("failed to serialize!");
-    } else {
-      unimplemented!();
+pub struct ArrayHeader {
+  pub data_ty:  u8,
+  pub dims:     Vec<usize>,
+}
+
+// Writes `elems` as consecutive little-endian values. On little-endian
+// hosts the in-memory bytes already have that layout and are written in
+// one call.
+pub fn write_elems_le<T>(elems: &[T], writer: &mut dyn Write) -> Result<(), ArrayIoError> where T: SerialDataType {
+  if cfg!(target_endian = "little") {
+    let bytes = unsafe { from_raw_parts(elems.as_ptr() as *const u8, size_of_val(elems)) };
+    writer.write_all(bytes)?;
+  } else {
+    for &x in elems.iter() {
+      x.write_le(writer)?;
     }
-    Ok(())
   }
+  Ok(())
 }
 
-impl<'a, T> Array<'a, T, (usize, usize)> for Array2d<T> where T: 'a + Copy {
-  type View     = Array2dView<'a, T>;
-  type ViewMut  = Array2dViewMut<'a, T>;
-
-  fn as_view(&'a self) -> Array2dView<'a, T> {
-    Array2dView{
-      data:     &self.data,
-      bound:    self.bound,
-      stride:   self.stride,
+// Writes the `bound`-shaped region of `data`, laid out with `stride`, as the
+// serialized payload (first axis fastest). Each lane along axis 0 is
+// contiguous even in a strided layout, so it is written in one call and no
+// compacted copy is made.
+fn write_lanes_le<T>(data: &[T], bound: (usize, usize, usize), stride: (usize, usize), writer: &mut dyn Write) -> Result<(), ArrayIoError> where T: SerialDataType {
+  // An empty region has no payload, and its lanes may not lie within `data`.
+  if bound.len() == 0 {
+    return Ok(());
+  }
+  for k in 0 .. bound.2 {
+    for j in 0 .. bound.1 {
+      let offset = (0, j, k).offset(stride);
+      write_elems_le(&data[offset .. offset + bound.0], writer)?;
     }
   }
+  Ok(())
+}
 
-  fn as_view_mut(&'a mut self) -> Array2dViewMut<'a, T> {
-    Array2dViewMut{
-      data:     &mut self.data,
-      bound:    self.bound,
-      stride:   self.stride,
+pub fn write_nd_header(writer: &mut dyn Write, data_ty: u8, dims: &[usize]) -> Result<(), ArrayIoError> {
+  writer.write_u8(b'N')?;
+  writer.write_u8(b'D')?;
+  writer.write_u8(0)?;
+  writer.write_u8(data_ty)?;
+  writer.write_u32::<LittleEndian>(dims.len() as u32)?;
+  for &dim in dims.iter() {
+    writer.write_u64::<LittleEndian>(dim as u64)?;
+  }
+  Ok(())
+}
+
+// The most dims a serialized header may declare.
+pub const MAX_NDIM: u32 = 32;
+
+// Writes a version 1 header, which stores each dim as a u32 rather than a
+// u64 for tools that expect 32-bit dims. Fails before writing anything if a
+// dim does not fit.
+pub fn write_nd_header_compat32(writer: &mut dyn Write, data_ty: u8, dims: &[usize]) -> Result<(), ArrayIoError> {
+  for &dim in dims.iter() {
+    if dim > u32::MAX as usize {
+      return Err(ArrayIoError::TooLarge);
     }
   }
+  writer.write_u8(b'N')?;
+  writer.write_u8(b'D')?;
+  writer.write_u8(1)?;
+  writer.write_u8(data_ty)?;
+  writer.write_u32::<LittleEndian>(dims.len() as u32)?;
+  for &dim in dims.iter() {
+    writer.write_u32::<LittleEndian>(dim as u32)?;
+  }
+  Ok(())
 }
 
-pub struct Array2dView<'a, T> where T: 'a + Copy {
-  data:     &'a [T],
-  bound:    (usize, usize),
-  stride:   usize,
+// Accepts both version 0 (u64 dims) and version 1 (u32 dims) headers.
+pub fn read_nd_header(reader: &mut dyn Read) -> Result<ArrayHeader, ArrayIoError> {
+  let magic0 = match reader.read_u8() {
+    Ok(magic0) => magic0,
+    Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Err(ArrayIoError::Empty),
+    Err(e) => return Err(ArrayIoError::Io(e)),
+  };
+  let magic1 = reader.read_u8()?;
+  if magic0 != b'N' || magic1 != b'D' {
+    return Err(ArrayIoError::BadMagic);
+  }
+  let version = reader.read_u8()?;
+  if version > 1 {
+    return Err(ArrayIoError::BadVersion);
+  }
+  let data_ty = reader.read_u8()?;
+  let ndim = reader.read_u32::<LittleEndian>()?;
+  // A corrupt `ndim` would otherwise make the dims loop run on unchecked.
+  if ndim > MAX_NDIM {
+    return Err(ArrayIoError::BadHeader);
+  }
+  let mut dims = vec![];
+  for _ in 0 .. ndim {
+    let dim = if version == 0 {
+      reader.read_u64::<LittleEndian>()? as usize
+    } else {
+      reader.read_u32::<LittleEndian>()? as usize
+    };
+    dims.push(dim);
+  }
+  Ok(ArrayHeader{
+    data_ty:  data_ty,
+    dims:     dims,
+  })
 }
 
-impl<'a, T> ArrayView<'a, T, (usize, usize)> for Array2dView<'a, T> where T: 'a + Copy {
-  fn bound(&self) -> (usize, usize) {
-    self.bound
+fn checked_payload_size(elem_size: usize, dims: &[usize]) -> Option<usize> {
+  let mut size = elem_size;
+  for &dim in dims.iter() {
+    size = size.checked_mul(dim)?;
   }
+  Some(size)
+}
 
-  fn stride(&self) -> usize {
-    self.stride
+fn read_bounded_payload<T>(reader: &mut dyn Read, dims: &[usize], max_bytes: usize) -> Result<Vec<T>, ArrayIoError> where T: Copy {
+  let header_size = 8 + 8 * dims.len();
+  let payload_size = checked_payload_size(size_of::<T>(), dims).ok_or(ArrayIoError::TooLarge)?;
+  if header_size.saturating_add(payload_size) > max_bytes {
+    return Err(ArrayIoError::TooLarge);
   }
+  read_payload(reader, dims)
+}
 
-  fn len(&self) -> usize {
-    self.bound.len()
+// Fills `data_bytes` from `reader`, reporting a short read as `Truncated`.
+fn read_payload_bytes(reader: &mut dyn Read, data_bytes: &mut [u8]) -> Result<(), ArrayIoError> {
+  // An empty payload (some bound is 0) reads nothing.
+  let mut read_idx: usize = 0;
+  while read_idx < data_bytes.len() {
+    match reader.read(&mut data_bytes[read_idx ..]) {
+      Ok(0) => break,
+      Ok(n) => read_idx += n,
+      Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+      Err(e) => return Err(ArrayIoError::Io(e)),
+    }
   }
-
-  unsafe fn as_ptr(&self) -> *const T {
-    self.data.as_ptr()
+  if read_idx < data_bytes.len() {
+    return Err(ArrayIoError::Truncated{expected: data_bytes.len(), got: read_idx});
   }
+  Ok(())
+}
 
-  fn view(self, lo: (usize, usize), hi: (usize, usize)) -> Array2dView<'a, T> {
-    // TODO(20151215)
-    unimplemented!();
+fn read_payload<T>(reader: &mut dyn Read, dims: &[usize]) -> Result<Vec<T>, ArrayIoError> where T: Copy {
+  let payload_size = checked_payload_size(size_of::<T>(), dims).ok_or(ArrayIoError::TooLarge)?;
+  let len = payload_size / max(size_of::<T>(), 1);
+  let mut data = unsafe { alloc_uninit(len) };
+  {
+    let data_bytes = unsafe { from_raw_parts_mut(data.as_mut_ptr() as *mut u8, payload_size) };
+    read_payload_bytes(reader, data_bytes)?;
   }
+  Ok(data)
 }
 
-impl<'a, T> Array2dView<'a, T> where T: 'a + Copy {
-  pub fn as_slice(&self) -> &[T] {
-    self.data
+pub trait NdArraySerialize<T, S> where T: SerialDataType + Copy, S: Shape {
+  // The payload is always the dense logical contents, so this depends only on
+  // the bound and not on the in-memory stride.
+  fn serial_size(bound: S) -> usize;
+  fn deserialize(reader: &mut dyn Read) -> Result<Self, ArrayIoError> where Self: Sized;
+  fn serialize(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError>;
+
+  fn data_type_id(&self) -> u8 {
+    T::serial_id()
   }
 }
 
-pub struct Array2dViewMut<'a, T> where T: 'a + Copy {
-  data:     &'a mut [T],
-  bound:    (usize, usize),
-  stride:   usize,
+// Object-safe counterpart to `NdArraySerialize`, so arrays of different
+// ranks and element types can be written through `Box<dyn ErasedSerialize>`.
+// The methods are named apart from `NdArraySerialize`'s so that calls stay
+// unambiguous with both traits in scope.
+pub trait ErasedSerialize {
+  fn serial_size_erased(&self) -> usize;
+  fn serialize_erased(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError>;
 }
 
-impl<'a, T> ArrayViewMut<'a, T, (usize, usize)> for Array2dViewMut<'a, T> where T: 'a + Copy {
-  fn bound(&self) -> (usize, usize) {
-    self.bound
+// In debug builds, uninitialized buffers are filled with a poison byte
+// pattern so that reads before writes are easier to spot.
+pub const UNINIT_POISON_BYTE: u8 = 0xa5;
+
+unsafe fn alloc_uninit<T>(len: usize) -> Vec<T> where T: Copy {
+  // Every element starts as the same byte pattern: the poison byte in debug
+  // builds and zero otherwise.
+  let byte = if cfg!(debug_assertions) { UNINIT_POISON_BYTE } else { 0 };
+  let mut fill = MaybeUninit::<T>::uninit();
+  write_bytes(fill.as_mut_ptr(), byte, 1);
+  vec![fill.assume_init(); len]
+}
+
+pub struct Array1d<T> where T: Copy {
+  data:     Vec<T>,
+  bound:    usize,
+}
+
+impl<T> Array1d<T> where T: Copy {
+  pub unsafe fn new(bound: usize) -> Array1d<T> {
+    let data = alloc_uninit(bound);
+    Array1d{
+      data:     data,
+      bound:    bound,
+    }
   }
 
-  fn stride(&self) -> usize {
-    self.stride
+  pub fn empty() -> Array1d<T> {
+    Array1d::with_data(Vec::new())
   }
 
-  fn len(&self) -> usize {
-    self.bound.len()
+  pub fn with_data(data: Vec<T>) -> Array1d<T> {
+    let bound = data.len();
+    Array1d{
+      data:     data,
+      bound:    bound,
+    }
   }
 
-  unsafe fn as_ptr(&self) -> *const T {
-    self.data.as_ptr()
+  pub fn as_slice(&self) -> &[T] {
+    &self.data
   }
 
-  unsafe fn as_mut_ptr(&mut self) -> *mut T {
-    self.data.as_mut_ptr()
+  pub fn as_mut_slice(&mut self) -> &mut [T] {
+    &mut self.data
   }
 
-  fn view_mut(self, lo: (usize, usize), hi: (usize, usize)) -> Array2dViewMut<'a, T> {
-    // TODO(20151215)
-    unimplemented!();
+  pub fn bound(&self) -> usize {
+    self.bound
   }
-}
 
-impl<'a, T> Array2dViewMut<'a, T> where T: 'a + Copy {
-  pub fn as_mut_slice(&mut self) -> &mut [T] {
-    self.data
+  pub fn is_empty(&self) -> bool {
+    self.bound == 0
+  }
+
+  pub fn memory_bytes(&self) -> usize {
+    self.data.capacity() * size_of::<T>()
   }
 }
 
-pub struct BitArray3d {
+pub struct Array2d<T> where T: Copy {
+  data:     Vec<T>,
+  bound:    (usize, usize),
+  stride:   usize,
+}
+
+impl<T> Array2d<T> where T: Copy {
+  pub unsafe fn new(bound: (usize, usize)) -> Array2d<T> {
+    let len = bound.len();
+    let data = alloc_uninit(len);
+    Array2d{
+      data:     data,
+      bound:    bound,
+      stride:   bound.to_least_stride(),
+    }
+  }
+
+  // A zero-length array with bound `(0, 0)`.
+  pub fn empty() -> Array2d<T> {
+    Array2d{
+      data:     Vec::new(),
+      bound:    (0, 0),
+      stride:   0,
+    }
+  }
+
+  pub fn from_elem(bound: (usize, usize), value: T) -> Array2d<T> {
+    let len = bound.len();
+    Array2d{
+      data:     vec![value; len],
+      bound:    bound,
+      stride:   bound.to_least_stride(),
+    }
+  }
+
+  // `rows[i][j]` becomes element `(i, j)`.
+  pub fn from_rows(rows: Vec<Vec<T>>) -> Array2d<T> {
+    let num_rows = rows.len();
+    let num_cols = if num_rows > 0 { rows[0].len() } else { 0 };
+    for row in rows.iter() {
+      assert_eq!(row.len(), num_cols);
+    }
+    let bound = (num_rows, num_cols);
+    let mut array = unsafe { Array2d::new(bound) };
+    for (i, row) in rows.iter().enumerate() {
+      for (j, &x) in row.iter().enumerate() {
+        array.data[(i, j).offset(array.stride)] = x;
+      }
+    }
+    array
+  }
+
+  // Inverse of `from_rows`.
+  pub fn to_nested_vec(&self) -> Vec<Vec<T>> {
+    let mut rows = Vec::with_capacity(self.bound.0);
+    for i in 0 .. self.bound.0 {
+      let mut row = Vec::with_capacity(self.bound.1);
+      for j in 0 .. self.bound.1 {
+        row.push(self.data[(i, j).offset(self.stride)]);
+      }
+      rows.push(row);
+    }
+    rows
+  }
+
+  pub fn as_slice(&self) -> &[T] {
+    &self.data
+  }
+
+  pub fn as_mut_slice(&mut self) -> &mut [T] {
+    &mut self.data
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.bound.len() == 0
+  }
+
+  pub fn memory_bytes(&self) -> usize {
+    self.data.capacity() * size_of::<T>()
+  }
+
+  pub fn get(&self, idx: (usize, usize)) -> Option<&T> {
+    match idx.checked_offset(self.bound, self.stride) {
+      Some(offset) => self.data.get(offset),
+      None => None,
+    }
+  }
+
+  pub fn get_mut(&mut self, idx: (usize, usize)) -> Option<&mut T> {
+    match idx.checked_offset(self.bound, self.stride) {
+      Some(offset) => self.data.get_mut(offset),
+      None => None,
+    }
+  }
+
+  pub fn row(&self, i: usize) -> Array1d<T> {
+    assert!(i < self.bound.0);
+    let mut row = unsafe { Array1d::new(self.bound.1) };
+    for (j, x) in row.data.iter_mut().enumerate() {
+      *x = self.data[(i, j).offset(self.stride)];
+    }
+    row
+  }
+
+  pub fn col(&self, j: usize) -> Array1d<T> {
+    assert!(j < self.bound.1);
+    let mut col = unsafe { Array1d::new(self.bound.0) };
+    for (i, x) in col.data.iter_mut().enumerate() {
+      *x = self.data[(i, j).offset(self.stride)];
+    }
+    col
+  }
+
+  // Axis 0 is the contiguous axis, so appending a row re-lays out the whole
+  // buffer; `push_col` is the cheap append direction.
+  pub fn push_row(&mut self, row: &[T]) {
+    assert_eq!(row.len(), self.bound.1);
+    let new_bound = (self.bound.0 + 1, self.bound.1);
+    let mut data = Vec::with_capacity(new_bound.len());
+    for (j, &x) in row.iter().enumerate() {
+      for i in 0 .. self.bound.0 {
+        data.push(self.data[(i, j).offset(self.stride)]);
+      }
+      data.push(x);
+    }
+    self.data = data;
+    self.bound = new_bound;
+    self.stride = new_bound.to_least_stride();
+  }
+
+  pub fn push_col(&mut self, col: &[T]) {
+    assert_eq!(col.len(), self.bound.0);
+    if self.stride != self.bound.to_least_stride() {
+      let mut data = Vec::with_capacity(self.bound.len() + col.len());
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          data.push(self.data[(i, j).offset(self.stride)]);
+        }
+      }
+      self.data = data;
+    }
+    self.data.truncate(self.bound.len());
+    self.data.extend_from_slice(col);
+    self.bound.1 += 1;
+    self.stride = self.bound.to_least_stride();
+  }
+
+  pub fn batches_axis0<'a>(&'a self, batch: usize) -> Array2dBatches<'a, T> {
+    assert!(batch > 0);
+    Array2dBatches{
+      array:  self,
+      batch:  batch,
+      pos:    0,
+    }
+  }
+
+  #[cfg(feature = "rand")]
+  pub fn shuffle_axis0<R>(&mut self, rng: &mut R) where R: Rng {
+    // Fisher-Yates over rows, swapping each row elementwise through the
+    // stride.
+    for i in (1 .. self.bound.0).rev() {
+      let r = rng.gen_range(0, i + 1);
+      if r != i {
+        for j in 0 .. self.bound.1 {
+          self.data.swap((i, j).offset(self.stride), (r, j).offset(self.stride));
+        }
+      }
+    }
+  }
+
+  pub fn gather_axis0(&self, indices: &[usize]) -> Array2d<T> {
+    for &idx in indices.iter() {
+      assert!(idx < self.bound.0);
+    }
+    let mut array = unsafe { Array2d::new((indices.len(), self.bound.1)) };
+    for j in 0 .. self.bound.1 {
+      for (i, &idx) in indices.iter().enumerate() {
+        array.data[(i, j).offset(array.stride)] = self.data[(idx, j).offset(self.stride)];
+      }
+    }
+    array
+  }
+
+  // Each column of the result is one `kernel`-sized window, flattened with
+  // axis 0 fastest; windows are ordered the same way. Windows that would
+  // overhang the edge are dropped.
+  pub fn im2col(&self, kernel: (usize, usize), stride: (usize, usize)) -> Array2d<T> {
+    assert!(kernel.0 > 0 && kernel.1 > 0);
+    assert!(stride.0 > 0 && stride.1 > 0);
+    let num_windows0 = if self.bound.0 >= kernel.0 { (self.bound.0 - kernel.0) / stride.0 + 1 } else { 0 };
+    let num_windows1 = if self.bound.1 >= kernel.1 { (self.bound.1 - kernel.1) / stride.1 + 1 } else { 0 };
+    let mut array = unsafe { Array2d::new((kernel.0 * kernel.1, num_windows0 * num_windows1)) };
+    let mut idx = 0;
+    for w1 in 0 .. num_windows1 {
+      for w0 in 0 .. num_windows0 {
+        for dj in 0 .. kernel.1 {
+          for di in 0 .. kernel.0 {
+            let i = w0 * stride.0 + di;
+            let j = w1 * stride.1 + dj;
+            array.data[idx] = self.data[(i, j).offset(self.stride)];
+            idx += 1;
+          }
+        }
+      }
+    }
+    array
+  }
+
+  pub fn diagonal(&self) -> Array1d<T> {
+    let n = min(self.bound.0, self.bound.1);
+    let mut diag = unsafe { Array1d::new(n) };
+    for (i, x) in diag.data.iter_mut().enumerate() {
+      *x = self.data[(i, i).offset(self.stride)];
+    }
+    diag
+  }
+
+  pub fn set_diagonal(&mut self, values: &[T]) {
+    let n = min(self.bound.0, self.bound.1);
+    assert_eq!(n, values.len());
+    for (i, &x) in values.iter().enumerate() {
+      self.data[(i, i).offset(self.stride)] = x;
+    }
+  }
+
+  // As with `row` and `col`, axis 0 indexes rows and axis 1 indexes columns.
+  pub fn flip_horizontal(&self) -> Array2d<T> {
+    let mut array = unsafe { Array2d::new(self.bound) };
+    for j in 0 .. self.bound.1 {
+      for i in 0 .. self.bound.0 {
+        array.data[(i, j).offset(array.stride)] = self.data[(i, self.bound.1 - 1 - j).offset(self.stride)];
+      }
+    }
+    array
+  }
+
+  pub fn flip_vertical(&self) -> Array2d<T> {
+    let mut array = unsafe { Array2d::new(self.bound) };
+    for j in 0 .. self.bound.1 {
+      for i in 0 .. self.bound.0 {
+        array.data[(i, j).offset(array.stride)] = self.data[(self.bound.0 - 1 - i, j).offset(self.stride)];
+      }
+    }
+    array
+  }
+
+  // Rotates counterclockwise by 90 degrees.
+  pub fn rotate90(&self) -> Array2d<T> {
+    let mut array = unsafe { Array2d::new((self.bound.1, self.bound.0)) };
+    for j in 0 .. self.bound.0 {
+      for i in 0 .. self.bound.1 {
+        array.data[(i, j).offset(array.stride)] = self.data[(j, self.bound.1 - 1 - i).offset(self.stride)];
+      }
+    }
+    array
+  }
+
+  // Rows are strided in this layout, so each row is yielded as references to
+  // its elements rather than as a slice.
+  pub fn rows_mut<'a>(&'a mut self) -> Array2dRowsMut<'a, T> {
+    let (len, remaining) = self.bound;
+    let mut rows: Vec<Vec<&'a mut T>> = (0 .. len).map(|_| Vec::with_capacity(remaining)).collect();
+    if len > 0 {
+      for col in self.data.chunks_mut(self.stride).take(remaining) {
+        for (row, x) in rows.iter_mut().zip(col.iter_mut()) {
+          row.push(x);
+        }
+      }
+    }
+    Array2dRowsMut{
+      rows:   rows.into_iter(),
+    }
+  }
+
+  // Columns are the contiguous lanes.
+  pub fn cols_mut<'a>(&'a mut self) -> Array2dColsMut<'a, T> {
+    let len = self.bound.0;
+    let stride = max(self.stride, 1);
+    Array2dColsMut{
+      chunks:     self.data.chunks_mut(stride),
+      len:        len,
+      remaining:  self.bound.1,
+    }
+  }
+
+  pub fn map_inplace<F>(&mut self, mut f: F) where F: FnMut(T) -> T {
+    for j in 0 .. self.bound.1 {
+      for i in 0 .. self.bound.0 {
+        let p = (i, j).offset(self.stride);
+        self.data[p] = f(self.data[p]);
+      }
+    }
+  }
+
+  pub fn fold<B, F>(&self, init: B, mut f: F) -> B where F: FnMut(B, T) -> B {
+    let mut acc = init;
+    for j in 0 .. self.bound.1 {
+      for i in 0 .. self.bound.0 {
+        acc = f(acc, self.data[(i, j).offset(self.stride)]);
+      }
+    }
+    acc
+  }
+
+  pub fn swap(&mut self, a: (usize, usize), b: (usize, usize)) {
+    assert!(a.0 < self.bound.0 && a.1 < self.bound.1);
+    assert!(b.0 < self.bound.0 && b.1 < self.bound.1);
+    self.data.swap(a.offset(self.stride), b.offset(self.stride));
+  }
+
+  // Buffer offset of `idx` under the stored stride, i.e. `i + j * stride`;
+  // axis 0 is the fastest varying. Does not check bounds.
+  pub fn linear_index(&self, idx: (usize, usize)) -> usize {
+    idx.offset(self.stride)
+  }
+
+  pub fn contains(&self, idx: (usize, usize)) -> bool {
+    idx.checked_offset(self.bound, self.stride).is_some()
+  }
+
+  pub fn ndim(&self) -> usize {
+    self.bound.ndim()
+  }
+
+  pub fn dim(&self, axis: usize) -> usize {
+    self.bound.dim(axis)
+  }
+}
+
+impl<T> Array2d<T> where T: Copy {
+  pub fn reinterpret_cast<U>(self) -> Result<Array2d<U>, CastError> where U: Copy {
+    if self.stride != self.bound.to_least_stride() {
+      return Err(CastError::NotContiguous);
+    }
+    // The contiguous axis 0 is the one that gets rescaled.
+    let row_bytes = size_of::<T>() * self.bound.0;
+    if size_of::<U>() == 0 || !row_bytes.is_multiple_of(size_of::<U>()) {
+      return Err(CastError::SizeMismatch);
+    }
+    if !(self.data.as_ptr() as usize).is_multiple_of(align_of::<U>()) {
+      return Err(CastError::Misaligned);
+    }
+    let new_bound = (row_bytes / size_of::<U>(), self.bound.1);
+    let byte_len = size_of::<T>() * self.data.len();
+    let cap_bytes = size_of::<T>() * self.data.capacity();
+    let data = if align_of::<T>() == align_of::<U>() && cap_bytes.is_multiple_of(size_of::<U>()) {
+      let mut src_data = self.data;
+      let ptr = src_data.as_mut_ptr();
+      forget(src_data);
+      unsafe { Vec::from_raw_parts(ptr as *mut U, byte_len / size_of::<U>(), cap_bytes / size_of::<U>()) }
+    } else {
+      // The allocation cannot be handed over with a different alignment, so
+      // fall back to a byte copy.
+      let len = byte_len / size_of::<U>();
+      let mut dst_data: Vec<U> = vec![unsafe { zeroed() }; len];
+      unsafe { copy_nonoverlapping(self.data.as_ptr() as *const u8, dst_data.as_mut_ptr() as *mut u8, byte_len) };
+      dst_data
+    };
+    Ok(Array2d{
+      data:     data,
+      bound:    new_bound,
+      stride:   new_bound.to_least_stride(),
+    })
+  }
+}
+
+// One step of Kahan-Babuska-Neumaier summation over a `(sum, compensation)`
+// pair; the compensated total is `sum + compensation`.
+fn neumaier_add(acc: (f32, f32), x: f32) -> (f32, f32) {
+  let (sum, c) = acc;
+  let t = sum + x;
+  if sum.abs() >= x.abs() {
+    (t, c + ((sum - t) + x))
+  } else {
+    (t, c + ((x - t) + sum))
+  }
+}
+
+impl Array2d<f32> {
+  pub fn sum_kahan(&self) -> f32 {
+    let (sum, c) = self.fold((0.0, 0.0), neumaier_add);
+    sum + c
+  }
+
+  pub fn mean(&self) -> f32 {
+    self.sum_kahan() / self.bound.len() as f32
+  }
+
+  pub fn has_nan(&self) -> bool {
+    self.fold(false, |acc, x| acc || x.is_nan())
+  }
+
+  pub fn has_inf(&self) -> bool {
+    self.fold(false, |acc, x| acc || x.is_infinite())
+  }
+
+  pub fn count_nan(&self) -> usize {
+    self.fold(0, |acc, x| if x.is_nan() { acc + 1 } else { acc })
+  }
+
+  // LU decomposition with partial pivoting, packed into one matrix (the unit
+  // lower factor below the diagonal, the upper factor on and above it).
+  // Returns the row permutation and its sign, or `None` if singular.
+  fn lu_decompose(&self) -> Option<(Array2d<f32>, Vec<usize>, f32)> {
+    assert_eq!(self.bound.0, self.bound.1);
+    let n = self.bound.0;
+    let mut lu = Array2d::from_elem((n, n), 0.0);
+    for j in 0 .. n {
+      for i in 0 .. n {
+        lu.data[(i, j).offset(lu.stride)] = self.data[(i, j).offset(self.stride)];
+      }
+    }
+    let mut perm: Vec<usize> = (0 .. n).collect();
+    let mut sign = 1.0;
+    for c in 0 .. n {
+      let mut pivot = c;
+      for i in c + 1 .. n {
+        if lu.data[(i, c).offset(lu.stride)].abs() > lu.data[(pivot, c).offset(lu.stride)].abs() {
+          pivot = i;
+        }
+      }
+      if lu.data[(pivot, c).offset(lu.stride)] == 0.0 {
+        return None;
+      }
+      if pivot != c {
+        for j in 0 .. n {
+          lu.data.swap((c, j).offset(lu.stride), (pivot, j).offset(lu.stride));
+        }
+        perm.swap(c, pivot);
+        sign = -sign;
+      }
+      let d = lu.data[(c, c).offset(lu.stride)];
+      for i in c + 1 .. n {
+        let f = lu.data[(i, c).offset(lu.stride)] / d;
+        lu.data[(i, c).offset(lu.stride)] = f;
+        for j in c + 1 .. n {
+          let u = lu.data[(c, j).offset(lu.stride)];
+          lu.data[(i, j).offset(lu.stride)] -= f * u;
+        }
+      }
+    }
+    Some((lu, perm, sign))
+  }
+
+  pub fn det(&self) -> f32 {
+    match self.lu_decompose() {
+      Some((lu, _, sign)) => {
+        let mut det = sign;
+        for i in 0 .. self.bound.0 {
+          det *= lu.data[(i, i).offset(lu.stride)];
+        }
+        det
+      }
+      None => 0.0,
+    }
+  }
+
+  pub fn inverse(&self) -> Option<Array2d<f32>> {
+    let (lu, perm, _) = self.lu_decompose()?;
+    let n = self.bound.0;
+    let mut inv = Array2d::from_elem((n, n), 0.0);
+    // Solve `A x = e_c` for each column `c`, by forward and then back
+    // substitution on the permuted identity column.
+    for c in 0 .. n {
+      let mut x = vec![0.0; n];
+      for i in 0 .. n {
+        let mut v = if perm[i] == c { 1.0 } else { 0.0 };
+        for (l, &xl) in x[ .. i].iter().enumerate() {
+          v -= lu.data[(i, l).offset(lu.stride)] * xl;
+        }
+        x[i] = v;
+      }
+      for i in (0 .. n).rev() {
+        let mut v = x[i];
+        for (l, &xl) in x.iter().enumerate().skip(i + 1) {
+          v -= lu.data[(i, l).offset(lu.stride)] * xl;
+        }
+        x[i] = v / lu.data[(i, i).offset(lu.stride)];
+      }
+      for (i, &xi) in x.iter().enumerate() {
+        inv.data[(i, c).offset(inv.stride)] = xi;
+      }
+    }
+    Some(inv)
+  }
+
+  pub fn recip_inplace(&mut self) {
+    self.map_inplace(|x| 1.0 / x);
+  }
+
+  pub fn exp_inplace(&mut self) {
+    self.map_inplace(|x| x.exp());
+  }
+
+  pub fn ln_inplace(&mut self) {
+    self.map_inplace(|x| x.ln());
+  }
+
+  pub fn sqrt_inplace(&mut self) {
+    self.map_inplace(|x| x.sqrt());
+  }
+
+  pub fn tanh_inplace(&mut self) {
+    self.map_inplace(|x| x.tanh());
+  }
+
+  pub fn softmax_axis(&mut self, axis: usize) {
+    assert!(axis < 2);
+    let (num_lanes, lane_len) = match axis {
+      0 => (self.bound.1, self.bound.0),
+      _ => (self.bound.0, self.bound.1),
+    };
+    for lane in 0 .. num_lanes {
+      let idx = |t: usize| match axis {
+        0 => (t, lane),
+        _ => (lane, t),
+      };
+      // Subtract the lane max before exponentiating for stability.
+      let mut max_x = f32::NEG_INFINITY;
+      for t in 0 .. lane_len {
+        let x = self.data[idx(t).offset(self.stride)];
+        if x > max_x {
+          max_x = x;
+        }
+      }
+      let mut sum = 0.0;
+      for t in 0 .. lane_len {
+        let p = idx(t).offset(self.stride);
+        let y = (self.data[p] - max_x).exp();
+        self.data[p] = y;
+        sum += y;
+      }
+      for t in 0 .. lane_len {
+        self.data[idx(t).offset(self.stride)] /= sum;
+      }
+    }
+  }
+}
+
+impl<T> Array2d<T> where T: Numeric {
+  // For floats, division follows IEEE 754: a nonzero value divided by zero is
+  // infinite and zero divided by zero is NaN. Integer division by zero
+  // panics.
+  pub fn div_inplace(&mut self, other: &Array2d<T>) {
+    assert_eq!(self.bound, other.bound);
+    for j in 0 .. self.bound.1 {
+      for i in 0 .. self.bound.0 {
+        let p = (i, j).offset(self.stride);
+        self.data[p] = self.data[p] / other.data[(i, j).offset(other.stride)];
+      }
+    }
+  }
+
+  // Matrix product with `bound.0` as the number of rows and `bound.1` as the
+  // number of columns, so an `(m, n)` times an `(n, p)` gives an `(m, p)`.
+  // The inner loop runs down a column, which is contiguous in this layout.
+  pub fn matmul(&self, rhs: &Array2d<T>) -> Array2d<T> {
+    assert_eq!(self.bound.1, rhs.bound.0);
+    let (m, n, p) = (self.bound.0, self.bound.1, rhs.bound.1);
+    let mut array = Array2d::from_elem((m, p), T::zero());
+    for j in 0 .. p {
+      for l in 0 .. n {
+        let b = rhs.data[(l, j).offset(rhs.stride)];
+        for i in 0 .. m {
+          let q = (i, j).offset(array.stride);
+          array.data[q] = array.data[q] + self.data[(i, l).offset(self.stride)] * b;
+        }
+      }
+    }
+    array
+  }
+
+  pub fn scatter_add_axis0(&mut self, indices: &[usize], src: &Array2d<T>) {
+    assert_eq!(src.bound.0, indices.len());
+    assert_eq!(src.bound.1, self.bound.1);
+    for &idx in indices.iter() {
+      assert!(idx < self.bound.0);
+    }
+    for j in 0 .. self.bound.1 {
+      for (i, &idx) in indices.iter().enumerate() {
+        let p = (idx, j).offset(self.stride);
+        self.data[p] = self.data[p] + src.data[(i, j).offset(src.stride)];
+      }
+    }
+  }
+
+  // Inverse of `im2col`: scatters each window column back into an array of
+  // `output_bound`, summing wherever windows overlap.
+  pub fn col2im(cols: &Array2d<T>, output_bound: (usize, usize), kernel: (usize, usize), stride: (usize, usize)) -> Array2d<T> {
+    assert!(kernel.0 > 0 && kernel.1 > 0);
+    assert!(stride.0 > 0 && stride.1 > 0);
+    let num_windows0 = if output_bound.0 >= kernel.0 { (output_bound.0 - kernel.0) / stride.0 + 1 } else { 0 };
+    let num_windows1 = if output_bound.1 >= kernel.1 { (output_bound.1 - kernel.1) / stride.1 + 1 } else { 0 };
+    assert_eq!(cols.bound, (kernel.0 * kernel.1, num_windows0 * num_windows1));
+    let mut array: Array2d<T> = Array2d::from_elem(output_bound, T::zero());
+    for w1 in 0 .. num_windows1 {
+      for w0 in 0 .. num_windows0 {
+        let w = w0 + w1 * num_windows0;
+        for dj in 0 .. kernel.1 {
+          for di in 0 .. kernel.0 {
+            let p = di + dj * kernel.0;
+            let offset = (w0 * stride.0 + di, w1 * stride.1 + dj).offset(array.stride);
+            array.data[offset] = array.data[offset] + cols.data[(p, w).offset(cols.stride)];
+          }
+        }
+      }
+    }
+    array
+  }
+}
+
+pub struct Array2dRowsMut<'a, T> where T: 'a + Copy {
+  rows:   IntoIter<Vec<&'a mut T>>,
+}
+
+impl<'a, T> Iterator for Array2dRowsMut<'a, T> where T: 'a + Copy {
+  type Item = Vec<&'a mut T>;
+
+  fn next(&mut self) -> Option<Vec<&'a mut T>> {
+    self.rows.next()
+  }
+}
+
+pub struct Array2dColsMut<'a, T> where T: 'a + Copy {
+  chunks:     ChunksMut<'a, T>,
+  len:        usize,
+  remaining:  usize,
+}
+
+impl<'a, T> Iterator for Array2dColsMut<'a, T> where T: 'a + Copy {
+  type Item = &'a mut [T];
+
+  fn next(&mut self) -> Option<&'a mut [T]> {
+    if self.remaining == 0 {
+      return None;
+    }
+    self.remaining -= 1;
+    match self.chunks.next() {
+      Some(chunk) => Some(&mut chunk[ .. self.len]),
+      None => None,
+    }
+  }
+}
+
+pub struct Array2dBatches<'a, T> where T: 'a + Copy {
+  array:  &'a Array2d<T>,
+  batch:  usize,
+  pos:    usize,
+}
+
+impl<'a, T> Iterator for Array2dBatches<'a, T> where T: 'a + Copy {
+  type Item = Array2dView<'a, T>;
+
+  fn next(&mut self) -> Option<Array2dView<'a, T>> {
+    let bound = self.array.bound;
+    if self.pos >= bound.0 {
+      return None;
+    }
+    let lo = self.pos;
+    let hi = min(lo + self.batch, bound.0);
+    self.pos = hi;
+    Some(self.array.as_view().view((lo, 0), (hi, bound.1)))
+  }
+}
+
+impl<T> ArrayZeroExt<T, (usize, usize)> for Array2d<T> where T: Zero + Copy {
+  fn zeros(bound: (usize, usize)) -> Array2d<T> {
+    let len = bound.len();
+    let data = vec![T::zero(); len];
+    Array2d{
+      data:     data,
+      bound:    bound,
+      stride:   bound.to_least_stride(),
+    }
+  }
+}
+
+impl<T> Array2d<T> where T: SerialDataType + Copy {
+  pub fn deserialize_bounded(reader: &mut dyn Read, max_bytes: usize) -> Result<Array2d<T>, ArrayIoError> {
+    let header = read_nd_header(reader)?;
+    if header.data_ty != T::serial_id() {
+      return Err(ArrayIoError::DataTypeMismatch);
+    }
+    if header.dims.len() != 2 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    let bound = (header.dims[0], header.dims[1]);
+    let data = read_bounded_payload(reader, &header.dims, max_bytes)?;
+    Ok(Array2d{
+      data:     data,
+      bound:    bound,
+      stride:   bound.to_least_stride(),
+    })
+  }
+
+  // Like `serialize`, but with a version 1 (32-bit dims) header; the usual
+  // `deserialize` reads it back.
+  pub fn serialize_compat32(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    let ty_id = T::serial_id();
+    let (bound0, bound1) = self.bound;
+    write_nd_header_compat32(writer, ty_id, &[bound0, bound1])?;
+    write_lanes_le(&self.data, (bound0, bound1, 1), (self.stride, bound1), writer)
+  }
+}
+
+impl<T> NdArraySerialize<T, (usize, usize)> for Array2d<T> where T: SerialDataType + Copy {
+  fn serial_size(bound: (usize, usize)) -> usize {
+    24 + size_of::<T>() * bound.len()
+  }
+
+  fn deserialize(reader: &mut dyn Read) -> Result<Array2d<T>, ArrayIoError> {
+    let header = read_nd_header(reader)?;
+    if header.data_ty != T::serial_id() {
+      return Err(ArrayIoError::DataTypeMismatch);
+    }
+    if header.dims.len() != 2 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    // The dims are untrusted; refuse ones whose payload size overflows
+    // before allocating for them.
+    if checked_payload_size(size_of::<T>(), &header.dims).is_none() {
+      return Err(ArrayIoError::TooLarge);
+    }
+    let dims = (header.dims[0], header.dims[1]);
+    let mut arr = unsafe { Array2d::new(dims) };
+    {
+      let data_bytes = unsafe { from_raw_parts_mut(arr.data.as_mut_ptr() as *mut u8, size_of::<T>() * arr.data.len()) };
+      read_payload_bytes(reader, data_bytes)?;
+    }
+    Ok(arr)
+  }
+
+  fn serialize(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    let ty_id = T::serial_id();
+    let (bound0, bound1) = self.bound;
+    write_nd_header(writer, ty_id, &[bound0, bound1])?;
+    if self.as_view().is_contiguous() {
+      write_elems_le(&self.data[ .. self.bound.len()], writer)?;
+    } else {
+      write_lanes_le(&self.data, (bound0, bound1, 1), (self.stride, bound1), writer)?;
+    }
+    Ok(())
+  }
+}
+
+impl<T> ErasedSerialize for Array2d<T> where T: SerialDataType + Copy {
+  fn serial_size_erased(&self) -> usize {
+    <Array2d<T> as NdArraySerialize<T, (usize, usize)>>::serial_size(self.bound)
+  }
+
+  fn serialize_erased(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    NdArraySerialize::serialize(self, writer)
+  }
+}
+
+impl<'a, T> Array<'a, T, (usize, usize)> for Array2d<T> where T: 'a + Copy {
+  type View     = Array2dView<'a, T>;
+  type ViewMut  = Array2dViewMut<'a, T>;
+
+  fn as_view(&'a self) -> Array2dView<'a, T> {
+    Array2dView{
+      data:     &self.data,
+      bound:    self.bound,
+      stride:   self.stride,
+    }
+  }
+
+  fn as_view_mut(&'a mut self) -> Array2dViewMut<'a, T> {
+    Array2dViewMut{
+      data:     &mut self.data,
+      bound:    self.bound,
+      stride:   self.stride,
+    }
+  }
+}
+
+// `stride` is the step between columns; elements within a column are always
+// adjacent. A transposed view would need a non-unit step along axis 0, which
+// this layout cannot express, so there is no zero-copy transpose.
+pub struct Array2dView<'a, T> where T: 'a + Copy {
+  data:     &'a [T],
+  bound:    (usize, usize),
+  stride:   usize,
+}
+
+impl<'a, T> ArrayView<'a, T, (usize, usize)> for Array2dView<'a, T> where T: 'a + Copy {
+  fn bound(&self) -> (usize, usize) {
+    self.bound
+  }
+
+  fn stride(&self) -> usize {
+    self.stride
+  }
+
+  unsafe fn as_ptr(&self) -> *const T {
+    self.data.as_ptr()
+  }
+
+  fn view(self, lo: (usize, usize), hi: (usize, usize)) -> Array2dView<'a, T> {
+    assert_view_range(lo, hi, self.bound);
+    let new_bound = (hi.0 - lo.0, hi.1 - lo.1);
+    let (new_offset, new_offset_end) = if new_bound.len() == 0 {
+      (0, 0)
+    } else {
+      (lo.offset(self.stride), (hi.0 - 1, hi.1 - 1).offset(self.stride) + 1)
+    };
+    Array2dView{
+      data:     &self.data[new_offset .. new_offset_end],
+      bound:    new_bound,
+      stride:   self.stride,
+    }
+  }
+}
+
+impl<'a, T> Array2dView<'a, T> where T: 'a + Copy {
+  pub fn as_slice(&self) -> &[T] {
+    self.data
+  }
+
+  // Like `view`, but reports an out-of-range region instead of panicking.
+  pub fn try_view(self, lo: (usize, usize), hi: (usize, usize)) -> Result<Array2dView<'a, T>, ViewError<(usize, usize)>> {
+    check_view_range(lo, hi, self.bound)?;
+    Ok(self.view(lo, hi))
+  }
+
+  pub fn to_owned(&self) -> Array2d<T> {
+    let mut array = unsafe { Array2d::new(self.bound) };
+    self.copy_into_slice(&mut array.data);
+    array
+  }
+
+  // Copies the logical elements, first axis fastest, into `dst` without
+  // any stride padding.
+  pub fn copy_into_slice(&self, dst: &mut [T]) {
+    assert_eq!(dst.len(), self.bound.len());
+    if self.bound.len() == 0 {
+      return;
+    }
+    // Each column is a contiguous run of `bound.0` elements.
+    let len = self.bound.0;
+    for j in 0 .. self.bound.1 {
+      let offset = (0, j).offset(self.stride);
+      dst[j * len .. (j + 1) * len].clone_from_slice(&self.data[offset .. offset + len]);
+    }
+  }
+
+  // Bounds-checked access; panics when `idx` is outside the view.
+  pub fn at(&self, idx: (usize, usize)) -> &T {
+    match idx.checked_offset(self.bound, self.stride) {
+      Some(offset) => &self.data[offset],
+      None => panic!("index out of bounds: {:?} for bound {:?}", idx, self.bound),
+    }
+  }
+
+  pub fn contains(&self, idx: (usize, usize)) -> bool {
+    idx.checked_offset(self.bound, self.stride).is_some()
+  }
+
+  pub fn ndim(&self) -> usize {
+    self.bound.ndim()
+  }
+
+  pub fn dim(&self, axis: usize) -> usize {
+    self.bound.dim(axis)
+  }
+}
+
+pub struct Array2dViewMut<'a, T> where T: 'a + Copy {
+  data:     &'a mut [T],
+  bound:    (usize, usize),
+  stride:   usize,
+}
+
+impl<'a, T> ArrayViewMut<'a, T, (usize, usize)> for Array2dViewMut<'a, T> where T: 'a + Copy {
+  fn bound(&self) -> (usize, usize) {
+    self.bound
+  }
+
+  fn stride(&self) -> usize {
+    self.stride
+  }
+
+  unsafe fn as_ptr(&self) -> *const T {
+    self.data.as_ptr()
+  }
+
+  unsafe fn as_mut_ptr(&mut self) -> *mut T {
+    self.data.as_mut_ptr()
+  }
+
+  fn view_mut(self, lo: (usize, usize), hi: (usize, usize)) -> Array2dViewMut<'a, T> {
+    assert_view_range(lo, hi, self.bound);
+    let new_bound = (hi.0 - lo.0, hi.1 - lo.1);
+    let (new_offset, new_offset_end) = if new_bound.len() == 0 {
+      (0, 0)
+    } else {
+      (lo.offset(self.stride), (hi.0 - 1, hi.1 - 1).offset(self.stride) + 1)
+    };
+    Array2dViewMut{
+      data:     &mut self.data[new_offset .. new_offset_end],
+      bound:    new_bound,
+      stride:   self.stride,
+    }
+  }
+}
+
+impl<'a, T> Array2dViewMut<'a, T> where T: 'a + Copy {
+  pub fn as_mut_slice(&mut self) -> &mut [T] {
+    self.data
+  }
+
+  // Like `view_mut`, but reports an out-of-range region instead of panicking.
+  pub fn try_view_mut(self, lo: (usize, usize), hi: (usize, usize)) -> Result<Array2dViewMut<'a, T>, ViewError<(usize, usize)>> {
+    check_view_range(lo, hi, self.bound)?;
+    Ok(self.view_mut(lo, hi))
+  }
+
+  // Bounds-checked access; panics when `idx` is outside the view.
+  pub fn at(&mut self, idx: (usize, usize)) -> &mut T {
+    match idx.checked_offset(self.bound, self.stride) {
+      Some(offset) => &mut self.data[offset],
+      None => panic!("index out of bounds: {:?} for bound {:?}", idx, self.bound),
+    }
+  }
+
+  pub fn contains(&self, idx: (usize, usize)) -> bool {
+    idx.checked_offset(self.bound, self.stride).is_some()
+  }
+
+  pub fn ndim(&self) -> usize {
+    self.bound.ndim()
+  }
+
+  pub fn dim(&self, axis: usize) -> usize {
+    self.bound.dim(axis)
+  }
+}
+
+// Shift within its u64 word of the MSB-first bit for linear index `idx`.
+fn msb_bit_shift(idx: usize) -> usize {
+  let s = idx % 64;
+  (s & !7) | (7 - (s & 7))
+}
+
+pub struct BitArray3d {
   data:     Vec<u64>,
   bound:    (usize, usize, usize),
   raw_len:  usize,
 }
 
-impl BitArray3d {
-  pub unsafe fn new(bound: (usize, usize, usize)) -> BitArray3d {
-    let len = bound.len();
-    let raw_len = (len + 64 - 1) / 64;
-    let mut data = Vec::with_capacity(raw_len);
-    unsafe { data.set_len(raw_len) };
-    BitArray3d{
-      data:     data,
-      bound:    bound,
-      raw_len:  raw_len,
+impl BitArray3d {
+  pub unsafe fn new(bound: (usize, usize, usize)) -> BitArray3d {
+    let len = bound.len();
+    let raw_len = len.div_ceil(64);
+    let data = alloc_uninit(raw_len);
+    BitArray3d{
+      data:     data,
+      bound:    bound,
+      raw_len:  raw_len,
+    }
+  }
+
+  pub fn empty() -> BitArray3d {
+    BitArray3d{
+      data:     Vec::new(),
+      bound:    (0, 0, 0),
+      raw_len:  0,
+    }
+  }
+
+  pub fn from_byte_array(arr: &Array3d<u8>) -> BitArray3d {
+    assert!(arr.stride == arr.bound.to_least_stride());
+    let mut raw_arr = unsafe { BitArray3d::new(arr.bound) };
+    let len = raw_arr.bound.len();
+    let raw_len = len.div_ceil(64);
+    {
+      let mut idx = 0;
+      for p in 0 .. raw_len {
+        raw_arr.data[p] = 0;
+        for s in 0 .. 64 {
+          if arr.data[idx] != 0 {
+            raw_arr.data[p] |= 1u64 << s;
+          }
+          idx += 1;
+          if idx >= len {
+            break;
+          }
+        }
+      }
+    }
+    raw_arr
+  }
+
+  // Like `from_byte_array`, but bits are packed MSB-first within each byte:
+  // linear index `8*b+s` is bit `7-s` of byte `b` of the little-endian words.
+  // Other methods assume the default LSB-first order, so use this only to
+  // exchange masks with other formats.
+  pub fn from_byte_array_msb(arr: &Array3d<u8>) -> BitArray3d {
+    assert!(arr.stride == arr.bound.to_least_stride());
+    let mut raw_arr = unsafe { BitArray3d::new(arr.bound) };
+    for p in 0 .. raw_arr.raw_len {
+      raw_arr.data[p] = 0;
+    }
+    let len = raw_arr.bound.len();
+    for idx in 0 .. len {
+      if arr.data[idx] != 0 {
+        raw_arr.data[idx / 64] |= 1u64 << msb_bit_shift(idx);
+      }
+    }
+    raw_arr
+  }
+
+  // Inverse of `from_byte_array_msb`.
+  pub fn into_bytes_msb(&self, nonzero_value: u8) -> Array3d<u8> {
+    let mut array = unsafe { Array3d::new(self.bound) };
+    let len = self.bound.len();
+    for idx in 0 .. len {
+      array.data[idx] = match (self.data[idx / 64] >> msb_bit_shift(idx)) & 1 {
+        0 => 0u8,
+        1 => nonzero_value,
+        _ => unreachable!(),
+      };
+    }
+    array
+  }
+
+  pub fn into_bytes(&self, nonzero_value: u8) -> Array3d<u8> {
+    let mut array = unsafe { Array3d::new(self.bound) };
+    let len = self.bound.len();
+    let raw_len = self.raw_len;
+    {
+      let mut idx = 0;
+      for p in 0 .. raw_len {
+        let mask = self.data[p];
+        for s in 0 .. 64 {
+          if 64 * p + s < len {
+            array.data[idx] = match (mask >> s) & 1 {
+              0 => 0u8,
+              1 => nonzero_value,
+              _ => unreachable!(),
+            };
+            idx += 1;
+          } else {
+            break;
+          }
+        }
+      }
+    }
+    array
+  }
+
+  pub fn write_bytes(&self, nonzero_value: u8, output: &mut Array3dViewMut<u8>) {
+    assert_eq!(self.bound(), output.bound());
+    assert_eq!(self.stride(), output.stride());
+    let len = self.bound.len();
+    let raw_len = self.raw_len;
+    {
+      let data = output.as_mut_slice();
+      let mut idx = 0;
+      for p in 0 .. raw_len {
+        let mask = self.data[p];
+        for s in 0 .. 64 {
+          if 64 * p + s < len {
+            data[idx] = match (mask >> s) & 1 {
+              0 => 0u8,
+              1 => nonzero_value,
+              _ => unreachable!(),
+            };
+            idx += 1;
+          } else {
+            break;
+          }
+        }
+      }
+    }
+  }
+
+  pub fn bound(&self) -> (usize, usize, usize) {
+    self.bound
+  }
+
+  pub fn stride(&self) -> (usize, usize) {
+    self.bound.to_least_stride()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.bound.len() == 0
+  }
+
+  pub fn memory_bytes(&self) -> usize {
+    self.raw_len * 8
+  }
+
+  // Counts the set bits in LSB-first order. Padding bits past the logical end
+  // are ignored, since a deserialized array may carry arbitrary ones there.
+  pub fn count_ones(&self) -> usize {
+    let len = self.bound.len();
+    let mut count = 0;
+    for p in 0 .. self.raw_len {
+      let mut mask = self.data[p];
+      let num_bits = len - 64 * p;
+      if num_bits < 64 {
+        mask &= (1u64 << num_bits) - 1;
+      }
+      count += mask.count_ones() as usize;
+    }
+    count
+  }
+
+  // Like `count_ones`, for arrays packed by `from_byte_array_msb`, where the
+  // valid bits of a partial last byte are its high bits.
+  pub fn count_ones_msb(&self) -> usize {
+    let len = self.bound.len();
+    let mut count = 0;
+    for p in 0 .. self.raw_len {
+      let mut mask = self.data[p];
+      let num_bits = len - 64 * p;
+      if num_bits < 64 {
+        let num_bytes = num_bits / 8;
+        let partial = num_bits % 8;
+        let mut valid = (1u64 << (8 * num_bytes)) - 1;
+        if partial > 0 {
+          valid |= ((0xff << (8 - partial)) & 0xff) << (8 * num_bytes);
+        }
+        mask &= valid;
+      }
+      count += mask.count_ones() as usize;
+    }
+    count
+  }
+
+  // Copies the bits in `[lo, hi)` into a new, densely packed bit array.
+  pub fn crop(&self, lo: (usize, usize, usize), hi: (usize, usize, usize)) -> BitArray3d {
+    assert!(lo.0 <= hi.0 && hi.0 <= self.bound.0);
+    assert!(lo.1 <= hi.1 && hi.1 <= self.bound.1);
+    assert!(lo.2 <= hi.2 && hi.2 <= self.bound.2);
+    let new_bound = (hi.0 - lo.0, hi.1 - lo.1, hi.2 - lo.2);
+    let mut raw_arr = unsafe { BitArray3d::new(new_bound) };
+    for p in 0 .. raw_arr.raw_len {
+      raw_arr.data[p] = 0;
+    }
+    let stride = self.stride();
+    let mut dst_idx = 0;
+    for k in lo.2 .. hi.2 {
+      for j in lo.1 .. hi.1 {
+        for i in lo.0 .. hi.0 {
+          let src_idx = (i, j, k).offset(stride);
+          if (self.data[src_idx / 64] >> (src_idx % 64)) & 1 != 0 {
+            raw_arr.data[dst_idx / 64] |= 1u64 << (dst_idx % 64);
+          }
+          dst_idx += 1;
+        }
+      }
+    }
+    raw_arr
+  }
+
+  // Indices of the set bits, in offset order.
+  pub fn iter_set<'a>(&'a self) -> BitArray3dSetIter<'a> {
+    BitArray3dSetIter{
+      array:    self,
+      word_idx: 0,
+      word:     if self.raw_len > 0 { self.data[0] } else { 0 },
+    }
+  }
+}
+
+pub struct BitArray3dSetIter<'a> {
+  array:    &'a BitArray3d,
+  word_idx: usize,
+  // The bits of `array.data[word_idx]` not yet yielded.
+  word:     u64,
+}
+
+impl<'a> Iterator for BitArray3dSetIter<'a> {
+  type Item = (usize, usize, usize);
+
+  fn next(&mut self) -> Option<(usize, usize, usize)> {
+    let raw_len = self.array.raw_len;
+    while self.word == 0 {
+      self.word_idx += 1;
+      if self.word_idx >= raw_len {
+        return None;
+      }
+      self.word = self.array.data[self.word_idx];
+    }
+    let s = self.word.trailing_zeros() as usize;
+    self.word &= self.word - 1;
+    let idx = 64 * self.word_idx + s;
+    let bound = self.array.bound;
+    if idx >= bound.len() {
+      // Padding bits past the logical end of the last word.
+      self.word = 0;
+      self.word_idx = raw_len;
+      return None;
+    }
+    Some(Shape::coord_from_offset(idx, bound.to_least_stride()))
+  }
+}
+
+impl BitArray3d {
+  pub fn serial_size(bound: (usize, usize, usize)) -> usize {
+    32 + bound.len().div_ceil(64) * 8
+  }
+
+  pub fn deserialize(reader: &mut dyn Read) -> Result<BitArray3d, ArrayIoError> {
+    let header = read_nd_header(reader)?;
+    if header.data_ty != 255 {
+      return Err(ArrayIoError::DataTypeMismatch);
+    }
+    if header.dims.len() != 3 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    let dims = (header.dims[0], header.dims[1], header.dims[2]);
+    let mut arr = unsafe { BitArray3d::new(dims) };
+    arr.read_raw_payload(reader)?;
+    Ok(arr)
+  }
+
+  fn read_raw_payload(&mut self, reader: &mut dyn Read) -> Result<(), ArrayIoError> {
+    let data_bytes = unsafe { from_raw_parts_mut(self.data.as_mut_ptr() as *mut u8, 8 * self.raw_len) };
+    read_payload_bytes(reader, data_bytes)
+  }
+
+  pub fn serialize(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    let ty_id = 255u8;
+    let (bound0, bound1, bound2) = self.bound;
+    write_nd_header(writer, ty_id, &[bound0, bound1, bound2])?;
+    let bytes = unsafe { from_raw_parts(self.data.as_ptr() as *const u8, 8 * self.raw_len) };
+    writer.write_all(bytes)?;
+    Ok(())
+  }
+
+  fn rle_runs(&self) -> Vec<u64> {
+    // Runs alternate between 0s and 1s, starting with a (possibly empty) run
+    // of 0s.
+    let len = self.bound.len();
+    let mut runs = vec![];
+    let mut bit = 0;
+    let mut run: u64 = 0;
+    for idx in 0 .. len {
+      let b = (self.data[idx / 64] >> (idx % 64)) & 1;
+      if b != bit {
+        runs.push(run);
+        bit = b;
+        run = 0;
+      }
+      run += 1;
+    }
+    runs.push(run);
+    runs
+  }
+
+  pub fn serialize_rle(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    let mut payload = vec![];
+    for &run in self.rle_runs().iter() {
+      // Run lengths are written as LEB128 varints.
+      let mut r = run;
+      loop {
+        if r < 0x80 {
+          payload.push(r as u8);
+          break;
+        }
+        payload.push(0x80 | (r & 0x7f) as u8);
+        r >>= 7;
+      }
+    }
+    // Fall back to the raw encoding when the runs do not save space.
+    let raw = 40 + payload.len() >= BitArray3d::serial_size(self.bound);
+    let ty_id = if raw { 255u8 } else { 254u8 };
+    let (bound0, bound1, bound2) = self.bound;
+    write_nd_header(writer, ty_id, &[bound0, bound1, bound2])?;
+    if raw {
+      let bytes = unsafe { from_raw_parts(self.data.as_ptr() as *const u8, 8 * self.raw_len) };
+      writer.write_all(bytes)?;
+    } else {
+      writer.write_u64::<LittleEndian>(payload.len() as u64)?;
+      writer.write_all(&payload)?;
+    }
+    Ok(())
+  }
+
+  // Reads either encoding written by `serialize_rle`. Malformed run data is
+  // reported as `BadHeader` rather than trusted.
+  pub fn deserialize_rle(reader: &mut dyn Read) -> Result<BitArray3d, ArrayIoError> {
+    let header = read_nd_header(reader)?;
+    let data_ty = header.data_ty;
+    if data_ty != 254 && data_ty != 255 {
+      return Err(ArrayIoError::DataTypeMismatch);
+    }
+    if header.dims.len() != 3 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    let len = match checked_payload_size(1, &header.dims) {
+      Some(len) => len,
+      None => return Err(ArrayIoError::TooLarge),
+    };
+    let dims = (header.dims[0], header.dims[1], header.dims[2]);
+    let mut arr = unsafe { BitArray3d::new(dims) };
+    if data_ty == 255 {
+      arr.read_raw_payload(reader)?;
+      return Ok(arr);
+    }
+    let payload_len = reader.read_u64::<LittleEndian>()?;
+    // An RLE payload is only written when it is smaller than the raw one.
+    if payload_len > BitArray3d::serial_size(dims) as u64 {
+      return Err(ArrayIoError::BadHeader);
+    }
+    let mut payload = vec![0u8; payload_len as usize];
+    reader.read_exact(&mut payload)?;
+    for p in arr.data.iter_mut() {
+      *p = 0;
+    }
+    let mut idx = 0;
+    let mut bit = 0;
+    let mut pos = 0;
+    while pos < payload.len() {
+      let mut run: u64 = 0;
+      let mut shift = 0;
+      loop {
+        // A varint must end within the payload and fit in a u64.
+        if pos >= payload.len() || shift >= 64 {
+          return Err(ArrayIoError::BadHeader);
+        }
+        let byte = payload[pos];
+        pos += 1;
+        run |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+          break;
+        }
+      }
+      if run > (len - idx) as u64 {
+        return Err(ArrayIoError::BadHeader);
+      }
+      let run = run as usize;
+      if bit == 1 {
+        for i in idx .. idx + run {
+          arr.data[i / 64] |= 1u64 << (i % 64);
+        }
+      }
+      idx += run;
+      bit ^= 1;
+    }
+    if idx != len {
+      return Err(ArrayIoError::BadHeader);
+    }
+    Ok(arr)
+  }
+}
+
+impl ErasedSerialize for BitArray3d {
+  fn serial_size_erased(&self) -> usize {
+    BitArray3d::serial_size(self.bound)
+  }
+
+  fn serialize_erased(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    self.serialize(writer)
+  }
+}
+
+// Maps padded index `p` to a source index along an axis of length `n` that
+// was padded by `amount` on each side.
+fn reflect_index(p: usize, amount: usize, n: usize) -> usize {
+  if p < amount {
+    amount - p
+  } else if p - amount < n {
+    p - amount
+  } else {
+    2 * (n - 1) + amount - p
+  }
+}
+
+fn wrap_index(p: usize, amount: usize, n: usize) -> usize {
+  (p + n - amount % n) % n
+}
+
+// Copies the `bound`-shaped region of `src` into `dst`, each laid out with its
+// own stride. Lanes along axis 0 are contiguous on both sides, so each one is
+// copied as a slice.
+fn strided_copy_3d<T>(src: &[T], src_stride: (usize, usize), dst: &mut [T], dst_stride: (usize, usize), bound: (usize, usize, usize)) where T: Copy {
+  if bound.len() == 0 {
+    return;
+  }
+  for k in 0 .. bound.2 {
+    for j in 0 .. bound.1 {
+      let src_offset = (0, j, k).offset(src_stride);
+      let dst_offset = (0, j, k).offset(dst_stride);
+      dst[dst_offset .. dst_offset + bound.0].clone_from_slice(&src[src_offset .. src_offset + bound.0]);
+    }
+  }
+}
+
+fn strided_fits(data_len: usize, bound: (usize, usize, usize), stride: (usize, usize)) -> bool {
+  if bound.0 > stride.0 || bound.1 > stride.1 {
+    return false;
+  }
+  if bound.len() == 0 {
+    return true;
+  }
+  let hi = (bound.0 - 1, bound.1 - 1, bound.2 - 1);
+  hi.offset(stride) < data_len
+}
+
+#[derive(Clone)]
+pub struct Array3d<T> where T: Copy {
+  data:     Vec<T>,
+  bound:    (usize, usize, usize),
+  stride:   (usize, usize),
+}
+
+impl<'a, T> Array<'a, T, (usize, usize, usize)> for Array3d<T> where T: 'a + Copy {
+  type View     = Array3dView<'a, T>;
+  type ViewMut  = Array3dViewMut<'a, T>;
+
+  fn as_view(&'a self) -> Array3dView<'a, T> {
+    Array3dView{
+      data:     &self.data,
+      bound:    self.bound,
+      stride:   self.stride,
+    }
+  }
+
+  fn as_view_mut(&'a mut self) -> Array3dViewMut<'a, T> {
+    Array3dViewMut{
+      data:     &mut self.data,
+      bound:    self.bound,
+      stride:   self.stride,
+    }
+  }
+}
+
+impl<T> Array3d<T> where T: Copy {
+  pub unsafe fn new(bound: (usize, usize, usize)) -> Array3d<T> {
+    let len = bound.len();
+    let data = alloc_uninit(len);
+    Array3d{
+      data:     data,
+      bound:    bound,
+      stride:   bound.to_least_stride(),
+    }
+  }
+
+  // A zero-length array with bound `(0, 0, 0)`.
+  pub fn empty() -> Array3d<T> {
+    Array3d{
+      data:     Vec::new(),
+      bound:    (0, 0, 0),
+      stride:   (0, 0),
+    }
+  }
+
+  pub fn from_elem(bound: (usize, usize, usize), value: T) -> Array3d<T> {
+    let len = bound.len();
+    Array3d{
+      data:     vec![value; len],
+      bound:    bound,
+      stride:   bound.to_least_stride(),
+    }
+  }
+
+  pub fn with_data(data: Vec<T>, bound: (usize, usize, usize)) -> Array3d<T> {
+    let len = bound.len();
+    assert_eq!(len, data.len());
+    Array3d{
+      data:     data,
+      bound:    bound,
+      stride:   bound.to_least_stride(),
+    }
+  }
+
+  pub fn with_data_strided(data: Vec<T>, bound: (usize, usize, usize), stride: (usize, usize)) -> Array3d<T> {
+    assert!(strided_fits(data.len(), bound, stride));
+    Array3d{
+      data:     data,
+      bound:    bound,
+      stride:   stride,
+    }
+  }
+
+  pub fn into_raw_parts(self) -> (Vec<T>, (usize, usize, usize), (usize, usize)) {
+    (self.data, self.bound, self.stride)
+  }
+
+  pub unsafe fn from_raw_parts(data: Vec<T>, bound: (usize, usize, usize), stride: (usize, usize)) -> Array3d<T> {
+    Array3d{
+      data:     data,
+      bound:    bound,
+      stride:   stride,
+    }
+  }
+
+  // Checks that the backing buffer covers every element addressed by the
+  // bound and stride. Safe constructors and deserialization always produce
+  // valid arrays; this is for ones assembled with `from_raw_parts`.
+  pub fn validate(&self) -> Result<(), ShapeError> {
+    if self.bound.0 > self.stride.0 || self.bound.1 > self.stride.1 {
+      return Err(ShapeError::StrideTooSmall);
+    }
+    if self.bound.len() == 0 {
+      return Ok(());
+    }
+    let hi = (self.bound.0 - 1, self.bound.1 - 1, self.bound.2 - 1);
+    let required = hi.offset(self.stride) + 1;
+    if self.data.len() < required {
+      return Err(ShapeError::BufferTooShort{required: required, len: self.data.len()});
+    }
+    Ok(())
+  }
+
+  pub fn from_raw_parts_checked(data: Vec<T>, bound: (usize, usize, usize), stride: (usize, usize)) -> Option<Array3d<T>> {
+    if strided_fits(data.len(), bound, stride) {
+      Some(Array3d{
+        data:     data,
+        bound:    bound,
+        stride:   stride,
+      })
+    } else {
+      None
+    }
+  }
+
+  pub fn as_slice(&self) -> &[T] {
+    &self.data
+  }
+
+  pub fn as_mut_slice(&mut self) -> &mut [T] {
+    &mut self.data
+  }
+
+  pub fn bound(&self) -> (usize, usize, usize) {
+    self.bound
+  }
+
+  pub fn stride(&self) -> (usize, usize) {
+    self.stride
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.bound.len() == 0
+  }
+
+  pub fn memory_bytes(&self) -> usize {
+    self.data.capacity() * size_of::<T>()
+  }
+
+  pub fn get(&self, idx: (usize, usize, usize)) -> Option<&T> {
+    match idx.checked_offset(self.bound, self.stride) {
+      Some(offset) => self.data.get(offset),
+      None => None,
+    }
+  }
+
+  pub fn get_mut(&mut self, idx: (usize, usize, usize)) -> Option<&mut T> {
+    match idx.checked_offset(self.bound, self.stride) {
+      Some(offset) => self.data.get_mut(offset),
+      None => None,
+    }
+  }
+
+  // Like `get_mut`, but panics when `idx` is out of bounds.
+  pub fn at(&mut self, idx: (usize, usize, usize)) -> &mut T {
+    match idx.checked_offset(self.bound, self.stride) {
+      Some(offset) => &mut self.data[offset],
+      None => panic!("index out of bounds: {:?} for bound {:?}", idx, self.bound),
+    }
+  }
+
+  pub fn map_inplace<F>(&mut self, mut f: F) where F: FnMut(T) -> T {
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          let p = (i, j, k).offset(self.stride);
+          self.data[p] = f(self.data[p]);
+        }
+      }
+    }
+  }
+
+  pub fn zip_map_inplace<F>(&mut self, other: &Array3d<T>, mut f: F) where F: FnMut(T, T) -> T {
+    assert_eq!(self.bound, other.bound);
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          let p = (i, j, k).offset(self.stride);
+          self.data[p] = f(self.data[p], other.data[(i, j, k).offset(other.stride)]);
+        }
+      }
+    }
+  }
+
+  pub fn fold<B, F>(&self, init: B, mut f: F) -> B where F: FnMut(B, T) -> B {
+    let mut acc = init;
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          acc = f(acc, self.data[(i, j, k).offset(self.stride)]);
+        }
+      }
+    }
+    acc
+  }
+
+  pub fn swap(&mut self, a: (usize, usize, usize), b: (usize, usize, usize)) {
+    assert!(a.0 < self.bound.0 && a.1 < self.bound.1 && a.2 < self.bound.2);
+    assert!(b.0 < self.bound.0 && b.1 < self.bound.1 && b.2 < self.bound.2);
+    self.data.swap(a.offset(self.stride), b.offset(self.stride));
+  }
+
+  pub fn flip_axis(&self, axis: usize) -> Array3d<T> {
+    let mut array = self.clone();
+    array.reverse_axis_inplace(axis);
+    array
+  }
+
+  pub fn reverse_axis_inplace(&mut self, axis: usize) {
+    let n = self.bound.dim(axis);
+    // Only the first half along `axis` is visited; each swaps with its mirror.
+    let half_bound = match axis {
+      0 => (n / 2, self.bound.1, self.bound.2),
+      1 => (self.bound.0, n / 2, self.bound.2),
+      2 => (self.bound.0, self.bound.1, n / 2),
+      _ => unreachable!(),
+    };
+    for k in 0 .. half_bound.2 {
+      for j in 0 .. half_bound.1 {
+        for i in 0 .. half_bound.0 {
+          let mirror = match axis {
+            0 => (n - 1 - i, j, k),
+            1 => (i, n - 1 - j, k),
+            2 => (i, j, n - 1 - k),
+            _ => unreachable!(),
+          };
+          self.data.swap((i, j, k).offset(self.stride), mirror.offset(self.stride));
+        }
+      }
+    }
+  }
+
+  // Calls `f` on each lane along `axis`. Lanes are gathered into a
+  // temporary buffer and scattered back afterwards, so `f` always sees a
+  // contiguous slice regardless of stride.
+  pub fn apply_along_axis<F>(&mut self, axis: usize, mut f: F) where F: FnMut(&mut [T]) {
+    let n = self.bound.dim(axis);
+    let outer_bound = match axis {
+      0 => (1, self.bound.1, self.bound.2),
+      1 => (self.bound.0, 1, self.bound.2),
+      2 => (self.bound.0, self.bound.1, 1),
+      _ => unreachable!(),
+    };
+    if n == 0 {
+      return;
+    }
+    let mut lane = Vec::with_capacity(n);
+    for k in 0 .. outer_bound.2 {
+      for j in 0 .. outer_bound.1 {
+        for i in 0 .. outer_bound.0 {
+          let idx = |t: usize| match axis {
+            0 => (t, j, k),
+            1 => (i, t, k),
+            _ => (i, j, t),
+          };
+          lane.clear();
+          for t in 0 .. n {
+            lane.push(self.data[idx(t).offset(self.stride)]);
+          }
+          f(&mut lane);
+          for (t, &x) in lane.iter().enumerate() {
+            self.data[idx(t).offset(self.stride)] = x;
+          }
+        }
+      }
+    }
+  }
+
+  pub fn into_slices_axis2(&self) -> Vec<Array2d<T>> {
+    let mut slices = Vec::with_capacity(self.bound.2);
+    for k in 0 .. self.bound.2 {
+      let mut slice = unsafe { Array2d::new((self.bound.0, self.bound.1)) };
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          slice.data[(i, j).offset(slice.stride)] = self.data[(i, j, k).offset(self.stride)];
+        }
+      }
+      slices.push(slice);
+    }
+    slices
+  }
+
+  pub fn stack_axis2(slices: &[Array2d<T>]) -> Array3d<T> {
+    assert!(!slices.is_empty());
+    let slice_bound = slices[0].bound;
+    let mut array = unsafe { Array3d::new((slice_bound.0, slice_bound.1, slices.len())) };
+    for (k, slice) in slices.iter().enumerate() {
+      assert_eq!(slice_bound, slice.bound);
+      for j in 0 .. slice_bound.1 {
+        for i in 0 .. slice_bound.0 {
+          array.data[(i, j, k).offset(array.stride)] = slice.data[(i, j).offset(slice.stride)];
+        }
+      }
+    }
+    array
+  }
+
+  pub fn select(mask: &BitArray3d, a: &Array3d<T>, b: &Array3d<T>) -> Array3d<T> {
+    assert_eq!(mask.bound, a.bound);
+    assert_eq!(mask.bound, b.bound);
+    let mut array = unsafe { Array3d::new(a.bound) };
+    let mut idx = 0;
+    for k in 0 .. a.bound.2 {
+      for j in 0 .. a.bound.1 {
+        for i in 0 .. a.bound.0 {
+          array.data[idx] = if (mask.data[idx / 64] >> (idx % 64)) & 1 != 0 {
+            a.data[(i, j, k).offset(a.stride)]
+          } else {
+            b.data[(i, j, k).offset(b.stride)]
+          };
+          idx += 1;
+        }
+      }
+    }
+    array
+  }
+
+  // Maps `(x, y, c)` to `(x + w * y, c)`; for a contiguous array both have
+  // the same linear offset, so the layout is unchanged.
+  pub fn flatten_channels(&self) -> Array2d<T> {
+    let (w, h, c) = self.bound;
+    let mut array = unsafe { Array2d::new((w * h, c)) };
+    let mut idx = 0;
+    for k in 0 .. c {
+      for j in 0 .. h {
+        for i in 0 .. w {
+          array.data[idx] = self.data[(i, j, k).offset(self.stride)];
+          idx += 1;
+        }
+      }
+    }
+    array
+  }
+
+  pub fn reshape(self, new_bound: (usize, usize, usize)) -> Array3d<T> {
+    assert_eq!(self.stride, self.bound.to_least_stride());
+    assert_eq!(self.bound.len(), new_bound.len());
+    Array3d::with_data(self.data, new_bound)
+  }
+
+  // Borrowing counterpart to `reshape`; copies the data into the new array.
+  pub fn try_reshape(&self, new_bound: (usize, usize, usize)) -> Result<Array3d<T>, ReshapeError> {
+    if self.stride != self.bound.to_least_stride() {
+      return Err(ReshapeError::NotContiguous);
+    }
+    let len = self.bound.len();
+    if len != new_bound.len() {
+      return Err(ReshapeError::LengthMismatch);
+    }
+    Ok(Array3d::with_data(self.data[ .. len].to_vec(), new_bound))
+  }
+
+  pub fn view_as_row_major<'a>(&'a self) -> Array3dView<'a, T> {
+    // Zero-copy: the axes are reversed, so element `(k, j, i)` of the view is
+    // element `[i, j, k]` of the buffer read in row-major (C) order. Only
+    // meaningful for contiguous arrays.
+    assert_eq!(self.stride, self.bound.to_least_stride());
+    let new_bound = (self.bound.2, self.bound.1, self.bound.0);
+    Array3dView{
+      data:     &self.data,
+      bound:    new_bound,
+      stride:   new_bound.to_least_stride(),
+    }
+  }
+
+  pub fn interior<'a>(&'a self, border: usize) -> Array3dView<'a, T> {
+    let (b0, b1, b2) = self.bound;
+    assert!(2 * border <= b0 && 2 * border <= b1 && 2 * border <= b2);
+    self.as_view().view((border, border, border), (b0 - border, b1 - border, b2 - border))
+  }
+
+  // Tiles the array with non-overlapping views of size `block`, first axis
+  // fastest; blocks along the far edges are clamped to the bound.
+  pub fn blocks<'a>(&'a self, block: (usize, usize, usize)) -> Array3dBlocks<'a, T> {
+    assert!(block.0 > 0 && block.1 > 0 && block.2 > 0);
+    Array3dBlocks{
+      array:  self,
+      block:  block,
+      pos:    (0, 0, 0),
+    }
+  }
+
+  // Pads each axis by `amount` on both sides, mirroring about the edge
+  // element (which is not repeated).
+  pub fn pad_reflect(&self, amount: (usize, usize, usize)) -> Array3d<T> {
+    assert!(amount.0 < self.bound.0 && amount.1 < self.bound.1 && amount.2 < self.bound.2,
+        "reflect padding {:?} exceeds bound {:?}", amount, self.bound);
+    self.pad_with(amount, reflect_index)
+  }
+
+  // Pads each axis by `amount` on both sides, wrapping around to the
+  // opposite edge.
+  pub fn pad_wrap(&self, amount: (usize, usize, usize)) -> Array3d<T> {
+    assert!(self.bound.len() > 0);
+    self.pad_with(amount, wrap_index)
+  }
+
+  fn pad_with(&self, amount: (usize, usize, usize), src_index: fn(usize, usize, usize) -> usize) -> Array3d<T> {
+    let new_bound = (
+        self.bound.0 + 2 * amount.0,
+        self.bound.1 + 2 * amount.1,
+        self.bound.2 + 2 * amount.2,
+    );
+    let mut array = unsafe { Array3d::new(new_bound) };
+    let mut idx = 0;
+    for k in 0 .. new_bound.2 {
+      let src_k = src_index(k, amount.2, self.bound.2);
+      for j in 0 .. new_bound.1 {
+        let src_j = src_index(j, amount.1, self.bound.1);
+        for i in 0 .. new_bound.0 {
+          let src_i = src_index(i, amount.0, self.bound.0);
+          array.data[idx] = self.data[(src_i, src_j, src_k).offset(self.stride)];
+          idx += 1;
+        }
+      }
+    }
+    array
+  }
+
+  // Buffer offset of `idx` under the stored stride, i.e.
+  // `i + j * stride.0 + k * stride.1 * stride.0`; axis 0 is the fastest
+  // varying. Does not check bounds.
+  pub fn linear_index(&self, idx: (usize, usize, usize)) -> usize {
+    idx.offset(self.stride)
+  }
+
+  pub fn contains(&self, idx: (usize, usize, usize)) -> bool {
+    idx.checked_offset(self.bound, self.stride).is_some()
+  }
+
+  pub fn ndim(&self) -> usize {
+    self.bound.ndim()
+  }
+
+  pub fn dim(&self, axis: usize) -> usize {
+    self.bound.dim(axis)
+  }
+}
+
+pub struct Array3dBlocks<'a, T> where T: 'a + Copy {
+  array:  &'a Array3d<T>,
+  block:  (usize, usize, usize),
+  // The low corner of the next block.
+  pos:    (usize, usize, usize),
+}
+
+impl<'a, T> Iterator for Array3dBlocks<'a, T> where T: 'a + Copy {
+  type Item = Array3dView<'a, T>;
+
+  fn next(&mut self) -> Option<Array3dView<'a, T>> {
+    let bound = self.array.bound;
+    if bound.len() == 0 || self.pos.2 >= bound.2 {
+      return None;
+    }
+    let lo = self.pos;
+    let hi = (
+        min(lo.0 + self.block.0, bound.0),
+        min(lo.1 + self.block.1, bound.1),
+        min(lo.2 + self.block.2, bound.2),
+    );
+    self.pos.0 = hi.0;
+    if self.pos.0 >= bound.0 {
+      self.pos.0 = 0;
+      self.pos.1 = hi.1;
+      if self.pos.1 >= bound.1 {
+        self.pos.1 = 0;
+        self.pos.2 = hi.2;
+      }
+    }
+    Some(self.array.as_view().view(lo, hi))
+  }
+}
+
+pub trait ArrayMismatch {
+  fn first_mismatch(&self, other: &Self) -> Option<String>;
+}
+
+impl<T> ArrayMismatch for Array2d<T> where T: PartialEq + Debug + Copy {
+  fn first_mismatch(&self, other: &Array2d<T>) -> Option<String> {
+    if self.bound != other.bound {
+      return Some(format!("bound {:?} != {:?}", self.bound, other.bound));
+    }
+    for j in 0 .. self.bound.1 {
+      for i in 0 .. self.bound.0 {
+        let x = self.data[(i, j).offset(self.stride)];
+        let y = other.data[(i, j).offset(other.stride)];
+        if x != y {
+          return Some(format!("at {:?}: {:?} != {:?}", (i, j), x, y));
+        }
+      }
+    }
+    None
+  }
+}
+
+impl<T> ArrayMismatch for Array3d<T> where T: PartialEq + Debug + Copy {
+  fn first_mismatch(&self, other: &Array3d<T>) -> Option<String> {
+    if self.bound != other.bound {
+      return Some(format!("bound {:?} != {:?}", self.bound, other.bound));
+    }
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          let x = self.data[(i, j, k).offset(self.stride)];
+          let y = other.data[(i, j, k).offset(other.stride)];
+          if x != y {
+            return Some(format!("at {:?}: {:?} != {:?}", (i, j, k), x, y));
+          }
+        }
+      }
+    }
+    None
+  }
+}
+
+impl<T> PartialEq for Array2d<T> where T: PartialEq + Copy {
+  fn eq(&self, other: &Array2d<T>) -> bool {
+    if self.bound != other.bound {
+      return false;
+    }
+    for j in 0 .. self.bound.1 {
+      for i in 0 .. self.bound.0 {
+        if self.data[(i, j).offset(self.stride)] != other.data[(i, j).offset(other.stride)] {
+          return false;
+        }
+      }
+    }
+    true
+  }
+}
+
+impl<T> PartialEq for Array3d<T> where T: PartialEq + Copy {
+  fn eq(&self, other: &Array3d<T>) -> bool {
+    if self.bound != other.bound {
+      return false;
+    }
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          if self.data[(i, j, k).offset(self.stride)] != other.data[(i, j, k).offset(other.stride)] {
+            return false;
+          }
+        }
+      }
+    }
+    true
+  }
+}
+
+impl<T> Eq for Array3d<T> where T: Eq + Copy {
+}
+
+// Both conversions reuse the buffer; with a unit last axis the 3d stride
+// only ever multiplies `k == 0`.
+impl<T> From<Array2d<T>> for Array3d<T> where T: Copy {
+  fn from(array: Array2d<T>) -> Array3d<T> {
+    let (bound0, bound1) = array.bound;
+    Array3d{
+      data:     array.data,
+      bound:    (bound0, bound1, 1),
+      stride:   (array.stride, bound1),
+    }
+  }
+}
+
+impl<T> TryFrom<Array3d<T>> for Array2d<T> where T: Copy {
+  // Gives back the array when its last axis is not 1.
+  type Error = Array3d<T>;
+
+  fn try_from(array: Array3d<T>) -> Result<Array2d<T>, Array3d<T>> {
+    if array.bound.2 != 1 {
+      return Err(array);
+    }
+    Ok(Array2d{
+      data:     array.data,
+      bound:    (array.bound.0, array.bound.1),
+      stride:   array.stride.0,
+    })
+  }
+}
+
+impl<T> Hash for Array3d<T> where T: Hash + Copy {
+  fn hash<H>(&self, state: &mut H) where H: Hasher {
+    self.bound.hash(state);
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          self.data[(i, j, k).offset(self.stride)].hash(state);
+        }
+      }
+    }
+  }
+}
+
+impl Array3d<u8> {
+  pub fn to_bit_array(&self) -> BitArray3d {
+    BitArray3d::from_byte_array(self)
+  }
+
+  pub fn saturating_add_inplace(&mut self, other: &Array3d<u8>) {
+    self.zip_map_inplace(other, |x, y| x.saturating_add(y));
+  }
+
+  pub fn saturating_sub_inplace(&mut self, other: &Array3d<u8>) {
+    self.zip_map_inplace(other, |x, y| x.saturating_sub(y));
+  }
+
+  // Expects a `(width, height, 3)` array and emits pixels in scanline order.
+  pub fn to_interleaved_rgb(&self) -> Vec<u8> {
+    assert_eq!(self.bound.2, 3);
+    let mut buf = Vec::with_capacity(self.bound.len());
+    for y in 0 .. self.bound.1 {
+      for x in 0 .. self.bound.0 {
+        for c in 0 .. 3 {
+          buf.push(self.data[(x, y, c).offset(self.stride)]);
+        }
+      }
     }
+    buf
   }
 
-  pub fn from_byte_array(arr: &Array3d<u8>) -> BitArray3d {
-    assert!(arr.stride == arr.bound.to_least_stride());
-    let mut raw_arr = unsafe { BitArray3d::new(arr.bound) };
-    let len = raw_arr.bound.len();
-    let raw_len = (len + 64 - 1) / 64;
-    {
-      let mut idx = 0;
-      for p in 0 .. raw_len {
-        raw_arr.data[p] = 0;
-        for s in 0 .. 64 {
-          if arr.data[idx] != 0 {
-            raw_arr.data[p] |= 1u64 << s;
-          }
+  // Computes `(x / 255 - mean) / std` for each element.
+  pub fn to_f32_normalized(&self, mean: f32, std: f32) -> Array3d<f32> {
+    let mut array = unsafe { Array3d::new(self.bound) };
+    let mut idx = 0;
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          let x = self.data[(i, j, k).offset(self.stride)] as f32;
+          array.data[idx] = (x / 255.0 - mean) / std;
           idx += 1;
-          if idx >= len {
-            break;
+        }
+      }
+    }
+    array
+  }
+}
+
+impl Array3d<i8> {
+  // Inverse of `Array3d<f32>::quantize_i8`: `x = scale * (q - zero_point)`.
+  pub fn dequantize_i8(&self, scale: f32, zero_point: i8) -> Array3d<f32> {
+    let mut array = unsafe { Array3d::new(self.bound) };
+    let mut idx = 0;
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          let q = self.data[(i, j, k).offset(self.stride)];
+          array.data[idx] = scale * (q as f32 - zero_point as f32);
+          idx += 1;
+        }
+      }
+    }
+    array
+  }
+}
+
+impl Array3d<i16> {
+  pub fn saturating_add_inplace(&mut self, other: &Array3d<i16>) {
+    self.zip_map_inplace(other, |x, y| x.saturating_add(y));
+  }
+
+  pub fn saturating_sub_inplace(&mut self, other: &Array3d<i16>) {
+    self.zip_map_inplace(other, |x, y| x.saturating_sub(y));
+  }
+}
+
+impl Array3d<f32> {
+  pub fn sum_kahan(&self) -> f32 {
+    let (sum, c) = self.fold((0.0, 0.0), neumaier_add);
+    sum + c
+  }
+
+  pub fn mean(&self) -> f32 {
+    self.sum_kahan() / self.bound.len() as f32
+  }
+
+  pub fn has_nan(&self) -> bool {
+    self.fold(false, |acc, x| acc || x.is_nan())
+  }
+
+  pub fn has_inf(&self) -> bool {
+    self.fold(false, |acc, x| acc || x.is_infinite())
+  }
+
+  pub fn count_nan(&self) -> usize {
+    self.fold(0, |acc, x| if x.is_nan() { acc + 1 } else { acc })
+  }
+
+  pub fn recip_inplace(&mut self) {
+    self.map_inplace(|x| 1.0 / x);
+  }
+
+  pub fn exp_inplace(&mut self) {
+    self.map_inplace(|x| x.exp());
+  }
+
+  pub fn ln_inplace(&mut self) {
+    self.map_inplace(|x| x.ln());
+  }
+
+  pub fn sqrt_inplace(&mut self) {
+    self.map_inplace(|x| x.sqrt());
+  }
+
+  pub fn tanh_inplace(&mut self) {
+    self.map_inplace(|x| x.tanh());
+  }
+
+  pub fn to_bit_array(&self, threshold: f32) -> BitArray3d {
+    let mut raw_arr = unsafe { BitArray3d::new(self.bound) };
+    for p in raw_arr.data.iter_mut() {
+      *p = 0;
+    }
+    let mut idx = 0;
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          if self.data[(i, j, k).offset(self.stride)] > threshold {
+            raw_arr.data[idx / 64] |= 1u64 << (idx % 64);
           }
+          idx += 1;
         }
       }
     }
     raw_arr
   }
 
-  pub fn into_bytes(&self, nonzero_value: u8) -> Array3d<u8> {
-    let mut array = unsafe { Array3d::new(self.bound) };
+  pub fn set_masked(&mut self, mask: &BitArray3d, value: f32) {
+    assert_eq!(self.bound, mask.bound);
     let len = self.bound.len();
-    let raw_len = self.raw_len;
-    {
-      let mut idx = 0;
-      for p in 0 .. raw_len {
-        let mask = self.data[p];
-        for s in 0 .. 64 {
-          if 64 * p + s < len {
-            array.data[idx] = match (mask >> s) & 1 {
-              0 => 0u8,
-              1 => nonzero_value,
-              _ => unreachable!(),
-            };
-            idx += 1;
-          } else {
-            break;
+    for p in 0 .. mask.raw_len {
+      let mut word = mask.data[p];
+      while word != 0 {
+        let s = word.trailing_zeros() as usize;
+        word &= word - 1;
+        let idx = 64 * p + s;
+        if idx >= len {
+          break;
+        }
+        let i = idx % self.bound.0;
+        let j = (idx / self.bound.0) % self.bound.1;
+        let k = idx / (self.bound.0 * self.bound.1);
+        self.data[(i, j, k).offset(self.stride)] = value;
+      }
+    }
+  }
+
+  pub fn argmax_axis(&self, axis: usize) -> Array2d<usize> {
+    assert!(axis < 3);
+    let (out_bound, axis_len) = match axis {
+      0 => ((self.bound.1, self.bound.2), self.bound.0),
+      1 => ((self.bound.0, self.bound.2), self.bound.1),
+      _ => ((self.bound.0, self.bound.1), self.bound.2),
+    };
+    let mut array = unsafe { Array2d::new(out_bound) };
+    for b in 0 .. out_bound.1 {
+      for a in 0 .. out_bound.0 {
+        let idx = |t: usize| match axis {
+          0 => (t, a, b),
+          1 => (a, t, b),
+          _ => (a, b, t),
+        };
+        let mut max_t = 0;
+        let mut max_x = f32::NEG_INFINITY;
+        for t in 0 .. axis_len {
+          let x = self.data[idx(t).offset(self.stride)];
+          // Ties keep the first occurrence.
+          if x > max_x {
+            max_t = t;
+            max_x = x;
           }
         }
+        array.data[(a, b).offset(array.stride)] = max_t;
       }
     }
     array
   }
 
-  pub fn write_bytes(&self, nonzero_value: u8, output: &mut Array3dViewMut<u8>) {
-    assert_eq!(self.bound(), output.bound());
-    assert_eq!(self.stride(), output.stride());
-    let len = self.bound.len();
-    let raw_len = self.raw_len;
-    {
-      let mut data = output.as_mut_slice();
-      let mut idx = 0;
-      for p in 0 .. raw_len {
-        let mask = self.data[p];
-        for s in 0 .. 64 {
-          if 64 * p + s < len {
-            data[idx] = match (mask >> s) & 1 {
-              0 => 0u8,
-              1 => nonzero_value,
-              _ => unreachable!(),
-            };
-            idx += 1;
+  // Reduces `axis`, leaving the remaining two axes in order.
+  pub fn mean_axis(&self, axis: usize) -> Array2d<f32> {
+    assert!(axis < 3);
+    let (out_bound, axis_len) = match axis {
+      0 => ((self.bound.1, self.bound.2), self.bound.0),
+      1 => ((self.bound.0, self.bound.2), self.bound.1),
+      _ => ((self.bound.0, self.bound.1), self.bound.2),
+    };
+    let mut array = unsafe { Array2d::new(out_bound) };
+    for b in 0 .. out_bound.1 {
+      for a in 0 .. out_bound.0 {
+        let idx = |t: usize| match axis {
+          0 => (t, a, b),
+          1 => (a, t, b),
+          _ => (a, b, t),
+        };
+        let mut acc = (0.0, 0.0);
+        for t in 0 .. axis_len {
+          acc = neumaier_add(acc, self.data[idx(t).offset(self.stride)]);
+        }
+        array.data[(a, b).offset(array.stride)] = (acc.0 + acc.1) / axis_len as f32;
+      }
+    }
+    array
+  }
+
+  // Population variance along `axis`, computed in two passes (mean first,
+  // then squared deviations) to avoid cancellation.
+  pub fn var_axis(&self, axis: usize) -> Array2d<f32> {
+    let mut array = self.mean_axis(axis);
+    let out_bound = array.bound;
+    let axis_len = match axis {
+      0 => self.bound.0,
+      1 => self.bound.1,
+      _ => self.bound.2,
+    };
+    for b in 0 .. out_bound.1 {
+      for a in 0 .. out_bound.0 {
+        let idx = |t: usize| match axis {
+          0 => (t, a, b),
+          1 => (a, t, b),
+          _ => (a, b, t),
+        };
+        let p = (a, b).offset(array.stride);
+        let mean = array.data[p];
+        let mut acc = (0.0, 0.0);
+        for t in 0 .. axis_len {
+          let d = self.data[idx(t).offset(self.stride)] - mean;
+          acc = neumaier_add(acc, d * d);
+        }
+        array.data[p] = (acc.0 + acc.1) / axis_len as f32;
+      }
+    }
+    array
+  }
+
+  pub fn cumsum_axis(&mut self, axis: usize) {
+    assert!(axis < 3);
+    // Iterating with `.0` fastest visits each predecessor along any axis
+    // before its successor, so one pass suffices.
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          let prev = match axis {
+            0 if i > 0 => (i - 1, j, k),
+            1 if j > 0 => (i, j - 1, k),
+            2 if k > 0 => (i, j, k - 1),
+            _ => continue,
+          };
+          let x = self.data[prev.offset(self.stride)];
+          self.data[(i, j, k).offset(self.stride)] += x;
+        }
+      }
+    }
+  }
+
+  pub fn to_u8_saturating(&self, scale: f32) -> Array3d<u8> {
+    let mut array = unsafe { Array3d::new(self.bound) };
+    let mut idx = 0;
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          let x = (scale * self.data[(i, j, k).offset(self.stride)]).round();
+          // NaN maps to 0.
+          array.data[idx] = if x.is_nan() || x <= 0.0 {
+            0
+          } else if x >= 255.0 {
+            255
           } else {
-            break;
-          }
+            x as u8
+          };
+          idx += 1;
         }
       }
     }
+    array
   }
 
-  pub fn bound(&self) -> (usize, usize, usize) {
-    self.bound
+  // Affine int8 quantization: `q = clamp(round(x / scale) + zero_point)`.
+  pub fn quantize_i8(&self, scale: f32, zero_point: i8) -> Array3d<i8> {
+    assert!(scale > 0.0);
+    let mut array = unsafe { Array3d::new(self.bound) };
+    let mut idx = 0;
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          let q = (self.data[(i, j, k).offset(self.stride)] / scale).round() + zero_point as f32;
+          // NaN maps to the zero point.
+          array.data[idx] = if q.is_nan() {
+            zero_point
+          } else if q <= -128.0 {
+            -128
+          } else if q >= 127.0 {
+            127
+          } else {
+            q as i8
+          };
+          idx += 1;
+        }
+      }
+    }
+    array
   }
+}
 
-  pub fn stride(&self) -> (usize, usize) {
-    self.bound.to_least_stride()
+impl<T> ArrayZeroExt<T, (usize, usize, usize)> for Array3d<T> where T: Zero + Copy {
+  fn zeros(bound: (usize, usize, usize)) -> Array3d<T> {
+    let len = bound.len();
+    let data = vec![T::zero(); len];
+    Array3d{
+      data:     data,
+      bound:    bound,
+      stride:   bound.to_least_stride(),
+    }
   }
 }
 
-impl BitArray3d {
-  pub fn serial_size(bound: (usize, usize, usize)) -> usize {
-    32 + (bound.len() + 64 - 1) / 64 * 8
-  }
-
-  pub fn deserialize(reader: &mut Read) -> Result<BitArray3d, ()> {
-    let magic0 = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    let magic1 = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    assert_eq!(magic0, b'N');
-    assert_eq!(magic1, b'D');
-    let version = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    assert_eq!(version, 0);
-    let data_ty = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    let ndim = reader.read_u32::<LittleEndian>()
-      .ok().expect("failed to deserialize!");
-    let expected_data_ty = 255u8;
-    assert_eq!(data_ty, expected_data_ty);
-    assert_eq!(ndim, 3);
-    let bound0 = reader.read_u64::<LittleEndian>()
-      .ok().expect("failed to deserialize!") as usize;
-    let bound1 = reader.read_u64::<LittleEndian>()
-      .ok().expect("failed to deserialize!") as usize;
-    let bound2 = reader.read_u64::<LittleEndian>()
-      .ok().expect("failed to deserialize!") as usize;
-    let dims = (bound0, bound1, bound2);
-    let mut arr = unsafe { BitArray3d::new(dims) };
+impl<T> Array3d<T> where T: Numeric {
+  // For floats, division follows IEEE 754: a nonzero value divided by zero is
+  // infinite and zero divided by zero is NaN. Integer division by zero
+  // panics.
+  pub fn div_inplace(&mut self, other: &Array3d<T>) {
+    assert_eq!(self.bound, other.bound);
+    for k in 0 .. self.bound.2 {
+      for j in 0 .. self.bound.1 {
+        for i in 0 .. self.bound.0 {
+          let p = (i, j, k).offset(self.stride);
+          self.data[p] = self.data[p] / other.data[(i, j, k).offset(other.stride)];
+        }
+      }
+    }
+  }
+
+  // Fills with 0, 1, 2, ... in offset order (first axis fastest). The last
+  // value, `bound.len() - 1`, must be representable in `T`; e.g. a u8 array
+  // holds at most 256 elements. Larger bounds overflow, which panics in
+  // debug builds.
+  pub fn iota(bound: (usize, usize, usize)) -> Array3d<T> {
+    let mut array = unsafe { Array3d::new(bound) };
+    let mut value = T::zero();
+    for (p, x) in array.data.iter_mut().enumerate() {
+      if p > 0 {
+        value = value + T::one();
+      }
+      *x = value;
+    }
+    array
+  }
+
+  pub fn add_inplace(&mut self, other: &Array3d<T>) {
+    self.zip_map_inplace(other, |x, y| x + y);
+  }
+
+  pub fn sub_inplace(&mut self, other: &Array3d<T>) {
+    self.zip_map_inplace(other, |x, y| x - y);
+  }
+
+  pub fn mul_inplace(&mut self, other: &Array3d<T>) {
+    self.zip_map_inplace(other, |x, y| x * y);
+  }
+
+  pub fn scale_inplace(&mut self, alpha: T) {
+    self.map_inplace(|x| alpha * x);
+  }
+
+  pub fn sum(&self) -> T {
+    self.fold(T::zero(), |acc, x| acc + x)
+  }
+}
+
+impl<T> Array3d<T> where T: SerialDataType + Copy {
+  pub fn deserialize_bounded(reader: &mut dyn Read, max_bytes: usize) -> Result<Array3d<T>, ArrayIoError> {
+    let header = read_nd_header(reader)?;
+    if header.data_ty != T::serial_id() {
+      return Err(ArrayIoError::DataTypeMismatch);
+    }
+    if header.dims.len() != 3 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    let bound = (header.dims[0], header.dims[1], header.dims[2]);
+    let data = read_bounded_payload(reader, &header.dims, max_bytes)?;
+    Ok(Array3d::with_data(data, bound))
+  }
+}
+
+impl<T> NdArraySerialize<T, (usize, usize, usize)> for Array3d<T> where T: SerialDataType + Copy {
+  fn serial_size(bound: (usize, usize, usize)) -> usize {
+    32 + size_of::<T>() * bound.len()
+  }
+
+  fn deserialize(reader: &mut dyn Read) -> Result<Array3d<T>, ArrayIoError> {
+    let header = read_nd_header(reader)?;
+    if header.data_ty != T::serial_id() {
+      return Err(ArrayIoError::DataTypeMismatch);
+    }
+    if header.dims.len() != 3 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    // The dims are untrusted; refuse ones whose payload size overflows
+    // before allocating for them.
+    if checked_payload_size(size_of::<T>(), &header.dims).is_none() {
+      return Err(ArrayIoError::TooLarge);
+    }
+    let dims = (header.dims[0], header.dims[1], header.dims[2]);
+    let mut arr = unsafe { Array3d::new(dims) };
     {
-      let mut data_bytes = unsafe { from_raw_parts_mut(arr.data.as_mut_ptr() as *mut u8, 8 * arr.raw_len) };
-      let mut read_idx: usize = 0;
-      loop {
-        match reader.read(&mut data_bytes[read_idx ..]) {
-          Ok(n) => {
-            read_idx += n;
-            if n == 0 {
-              break;
-            }
-          }
-          Err(e) => panic!("failed to deserialize: {:?}", e),
+      let data_bytes = unsafe { from_raw_parts_mut(arr.data.as_mut_ptr() as *mut u8, size_of::<T>() * arr.data.len()) };
+      read_payload_bytes(reader, data_bytes)?;
+    }
+    Ok(arr)
+  }
+
+  fn serialize(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    let ty_id = T::serial_id();
+    let (bound0, bound1, bound2) = self.bound;
+    write_nd_header(writer, ty_id, &[bound0, bound1, bound2])?;
+    if self.as_view().is_contiguous() {
+      write_elems_le(&self.data[ .. self.bound.len()], writer)?;
+    } else {
+      write_lanes_le(&self.data, self.bound, self.stride, writer)?;
+    }
+    Ok(())
+  }
+}
+
+impl<T> ErasedSerialize for Array3d<T> where T: SerialDataType + Copy {
+  fn serial_size_erased(&self) -> usize {
+    <Array3d<T> as NdArraySerialize<T, (usize, usize, usize)>>::serial_size(self.bound)
+  }
+
+  fn serialize_erased(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    NdArraySerialize::serialize(self, writer)
+  }
+}
+
+impl<T> Array3d<T> where T: SerialDataType + Copy {
+  // Unlike `serialize`, writes each element explicitly as little-endian, so
+  // the output is identical on any host.
+  pub fn serialize_canonical(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    let ty_id = T::serial_id();
+    let (bound0, bound1, bound2) = self.bound;
+    write_nd_header(writer, ty_id, &[bound0, bound1, bound2])?;
+    for k in 0 .. bound2 {
+      for j in 0 .. bound1 {
+        for i in 0 .. bound0 {
+          self.data[(i, j, k).offset(self.stride)].write_le(writer)?;
         }
       }
-      assert_eq!(read_idx, data_bytes.len());
     }
-    Ok(arr)
+    Ok(())
+  }
+
+  // Like `serialize`, but with a version 1 (32-bit dims) header; the usual
+  // `deserialize` reads it back.
+  pub fn serialize_compat32(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    let ty_id = T::serial_id();
+    let (bound0, bound1, bound2) = self.bound;
+    write_nd_header_compat32(writer, ty_id, &[bound0, bound1, bound2])?;
+    write_lanes_le(&self.data, self.bound, self.stride, writer)
+  }
+
+  pub fn deserialize_canonical(reader: &mut dyn Read) -> Result<Array3d<T>, ArrayIoError> {
+    let header = read_nd_header(reader)?;
+    if header.data_ty != T::serial_id() {
+      return Err(ArrayIoError::DataTypeMismatch);
+    }
+    if header.dims.len() != 3 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    if checked_payload_size(size_of::<T>(), &header.dims).is_none() {
+      return Err(ArrayIoError::TooLarge);
+    }
+    let dims = (header.dims[0], header.dims[1], header.dims[2]);
+    // Grow as elements arrive so a truncated stream cannot force a huge
+    // up-front allocation.
+    let mut data = vec![];
+    for _ in 0 .. dims.len() {
+      data.push(T::read_le(reader)?);
+    }
+    Ok(Array3d::with_data(data, dims))
+  }
+}
+
+// A 3d array whose element type is only known once its header is read.
+pub enum AnyArray3d {
+  U8(Array3d<u8>),
+  F32(Array3d<f32>),
+  Bit(BitArray3d),
+}
+
+impl AnyArray3d {
+  pub fn deserialize(reader: &mut dyn Read) -> Result<AnyArray3d, ArrayIoError> {
+    let header = read_nd_header(reader)?;
+    AnyArray3d::read_typed_payload(&header, reader)
+  }
+
+  // Reads the payload following `header`, dispatching on its dtype.
+  fn read_typed_payload(header: &ArrayHeader, reader: &mut dyn Read) -> Result<AnyArray3d, ArrayIoError> {
+    if header.dims.len() != 3 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    let bound = (header.dims[0], header.dims[1], header.dims[2]);
+    if header.data_ty == <u8 as SerialDataType>::serial_id() {
+      let data = read_payload(reader, &header.dims)?;
+      Ok(AnyArray3d::U8(Array3d::with_data(data, bound)))
+    } else if header.data_ty == <f32 as SerialDataType>::serial_id() {
+      let data = read_payload(reader, &header.dims)?;
+      Ok(AnyArray3d::F32(Array3d::with_data(data, bound)))
+    } else if header.data_ty == 255 {
+      // Bit arrays carry packed u64 words rather than one element per
+      // index, so the payload is `serial_size` minus the header.
+      let raw_len = bound.len().div_ceil(64);
+      let data = read_payload(reader, &[raw_len])?;
+      Ok(AnyArray3d::Bit(BitArray3d{
+        data:     data,
+        bound:    bound,
+        raw_len:  raw_len,
+      }))
+    } else {
+      Err(ArrayIoError::DataTypeMismatch)
+    }
+  }
+
+  pub fn data_type_id(&self) -> u8 {
+    match *self {
+      AnyArray3d::U8(ref arr) => arr.data_type_id(),
+      AnyArray3d::F32(ref arr) => arr.data_type_id(),
+      AnyArray3d::Bit(_) => 255,
+    }
+  }
+
+  pub fn bound(&self) -> (usize, usize, usize) {
+    match *self {
+      AnyArray3d::U8(ref arr) => arr.bound(),
+      AnyArray3d::F32(ref arr) => arr.bound(),
+      AnyArray3d::Bit(ref arr) => arr.bound(),
+    }
+  }
+
+  pub fn as_u8(&self) -> Option<&Array3d<u8>> {
+    match *self {
+      AnyArray3d::U8(ref arr) => Some(arr),
+      _ => None,
+    }
+  }
+
+  pub fn as_f32(&self) -> Option<&Array3d<f32>> {
+    match *self {
+      AnyArray3d::F32(ref arr) => Some(arr),
+      _ => None,
+    }
+  }
+
+  pub fn as_bit(&self) -> Option<&BitArray3d> {
+    match *self {
+      AnyArray3d::Bit(ref arr) => Some(arr),
+      _ => None,
+    }
+  }
+
+  pub fn into_u8(self) -> Option<Array3d<u8>> {
+    match self {
+      AnyArray3d::U8(arr) => Some(arr),
+      _ => None,
+    }
+  }
+
+  pub fn into_f32(self) -> Option<Array3d<f32>> {
+    match self {
+      AnyArray3d::F32(arr) => Some(arr),
+      _ => None,
+    }
   }
 
-  pub fn serialize(&self, writer: &mut Write) -> Result<(), ()> {
-    let ty_id = 255u8;
-    writer.write_u32::<LittleEndian>(0x0000444e | ((ty_id as u32) << 24))
-      .ok().expect("failed to serialize!");
-    writer.write_u32::<LittleEndian>(3)
-      .ok().expect("failed to serialize!");
-    let (bound0, bound1, bound2) = self.bound;
-    writer.write_u64::<LittleEndian>(bound0 as u64)
-      .ok().expect("failed to serialize!");
-    writer.write_u64::<LittleEndian>(bound1 as u64)
-      .ok().expect("failed to serialize!");
-    writer.write_u64::<LittleEndian>(bound2 as u64)
-      .ok().expect("failed to serialize!");
-    let bytes = unsafe { from_raw_parts(self.data.as_ptr() as *const u8, 8 * self.raw_len) };
-    writer.write_all(bytes)
-      .ok().expect("failed to serialize!");
-    Ok(())
+  pub fn into_bit(self) -> Option<BitArray3d> {
+    match self {
+      AnyArray3d::Bit(arr) => Some(arr),
+      _ => None,
+    }
   }
 }
 
-#[derive(Clone)]
-pub struct Array3d<T> where T: Copy {
-  data:     Vec<T>,
-  bound:    (usize, usize, usize),
-  stride:   (usize, usize),
+// Reads consecutive serialized 3d arrays of mixed dtypes from one stream,
+// choosing the variant from each record's header.
+pub struct StreamArrayReader<R> where R: Read {
+  reader:   R,
 }
 
-impl<'a, T> Array<'a, T, (usize, usize, usize)> for Array3d<T> where T: 'a + Copy {
-  type View     = Array3dView<'a, T>;
-  type ViewMut  = Array3dViewMut<'a, T>;
-
-  fn as_view(&'a self) -> Array3dView<'a, T> {
-    Array3dView{
-      data:     &self.data,
-      bound:    self.bound,
-      stride:   self.stride,
+impl<R> StreamArrayReader<R> where R: Read {
+  pub fn new(reader: R) -> StreamArrayReader<R> {
+    StreamArrayReader{
+      reader:   reader,
     }
   }
 
-  fn as_view_mut(&'a mut self) -> Array3dViewMut<'a, T> {
-    Array3dViewMut{
-      data:     &mut self.data,
-      bound:    self.bound,
-      stride:   self.stride,
-    }
+  pub fn into_inner(self) -> R {
+    self.reader
+  }
+
+  // Returns `Ok(None)` once the stream ends cleanly between records.
+  pub fn next_array(&mut self) -> Result<Option<AnyArray3d>, ArrayIoError> {
+    let header = match read_nd_header(&mut self.reader) {
+      Ok(header) => header,
+      Err(ArrayIoError::Empty) => return Ok(None),
+      Err(e) => return Err(e),
+    };
+    AnyArray3d::read_typed_payload(&header, &mut self.reader).map(Some)
   }
 }
 
-impl<T> Array3d<T> where T: Copy {
-  pub unsafe fn new(bound: (usize, usize, usize)) -> Array3d<T> {
-    let len = bound.len();
-    let mut data = Vec::with_capacity(len);
-    data.set_len(len);
-    Array3d{
-      data:     data,
-      bound:    bound,
-      stride:   bound.to_least_stride(),
+impl<R> Iterator for StreamArrayReader<R> where R: Read {
+  type Item = Result<AnyArray3d, ArrayIoError>;
+
+  fn next(&mut self) -> Option<Result<AnyArray3d, ArrayIoError>> {
+    match self.next_array() {
+      Ok(Some(arr)) => Some(Ok(arr)),
+      Ok(None) => None,
+      Err(e) => Some(Err(e)),
     }
   }
+}
 
-  pub fn with_data(data: Vec<T>, bound: (usize, usize, usize)) -> Array3d<T> {
-    let len = bound.len();
-    assert_eq!(len, data.len());
-    Array3d{
-      data:     data,
+// Like `Array3d`, but backed by an allocation with a caller-chosen alignment
+// (e.g. for SIMD loads or device transfers). `Vec` always deallocates with the
+// element alignment, so the buffer is managed manually.
+pub struct AlignedArray3d<T> where T: Copy {
+  ptr:      *mut T,
+  len:      usize,
+  align:    usize,
+  bound:    (usize, usize, usize),
+  stride:   (usize, usize),
+}
+
+impl<T> AlignedArray3d<T> where T: Copy {
+  pub unsafe fn new(bound: (usize, usize, usize), align: usize) -> AlignedArray3d<T> {
+    assert!(align.is_power_of_two());
+    let align = max(align, align_of::<T>());
+    let len = bound.0.checked_mul(bound.1).and_then(|n| n.checked_mul(bound.2))
+      .expect("aligned array length overflows usize");
+    let size = len.checked_mul(size_of::<T>())
+      .expect("aligned array size overflows usize");
+    let ptr = if size == 0 {
+      align as *mut T
+    } else {
+      let ptr = alloc(Layout::from_size_align(size, align).unwrap()) as *mut T;
+      assert!(!ptr.is_null());
+      if cfg!(debug_assertions) {
+        write_bytes(ptr, UNINIT_POISON_BYTE, len);
+      }
+      ptr
+    };
+    AlignedArray3d{
+      ptr:      ptr,
+      len:      len,
+      align:    align,
       bound:    bound,
       stride:   bound.to_least_stride(),
     }
   }
 
+  pub fn align(&self) -> usize {
+    self.align
+  }
+
+  pub fn as_ptr(&self) -> *const T {
+    self.ptr
+  }
+
+  pub fn as_mut_ptr(&mut self) -> *mut T {
+    self.ptr
+  }
+
   pub fn as_slice(&self) -> &[T] {
-    &self.data
+    unsafe { from_raw_parts(self.ptr, self.len) }
   }
 
   pub fn as_mut_slice(&mut self) -> &mut [T] {
-    &mut self.data
+    unsafe { from_raw_parts_mut(self.ptr, self.len) }
   }
 
   pub fn bound(&self) -> (usize, usize, usize) {
@@ -599,94 +3448,45 @@ impl<T> Array3d<T> where T: Copy {
   pub fn stride(&self) -> (usize, usize) {
     self.stride
   }
+
+  pub fn is_empty(&self) -> bool {
+    self.bound.len() == 0
+  }
+
+  pub fn memory_bytes(&self) -> usize {
+    self.len * size_of::<T>()
+  }
 }
 
-impl<T> ArrayZeroExt<T, (usize, usize, usize)> for Array3d<T> where T: Zero + Copy {
-  fn zeros(bound: (usize, usize, usize)) -> Array3d<T> {
-    let len = bound.len();
-    let mut data = Vec::with_capacity(len);
-    unsafe { data.set_len(len) };
-    for i in 0 .. len {
-      data[i] = T::zero();
-    }
-    Array3d{
-      data:     data,
-      bound:    bound,
-      stride:   bound.to_least_stride(),
+impl<T> Drop for AlignedArray3d<T> where T: Copy {
+  fn drop(&mut self) {
+    let size = size_of::<T>() * self.len;
+    if size != 0 {
+      unsafe { dealloc(self.ptr as *mut u8, Layout::from_size_align(size, self.align).unwrap()) };
     }
   }
 }
 
-impl<T> NdArraySerialize<T, (usize, usize, usize)> for Array3d<T> where T: SerialDataType + Copy {
-  fn serial_size(bound: (usize, usize, usize)) -> usize {
-    32 + bound.len()
-  }
-
-  fn deserialize(reader: &mut Read) -> Result<Array3d<T>, ()> {
-    let magic0 = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    let magic1 = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    assert_eq!(magic0, b'N');
-    assert_eq!(magic1, b'D');
-    let version = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    assert_eq!(version, 0);
-    let data_ty = reader.read_u8()
-      .ok().expect("failed to deserialize!");
-    let ndim = reader.read_u32::<LittleEndian>()
-      .ok().expect("failed to deserialize!");
-    let expected_data_ty = T::serial_id();
-    assert_eq!(data_ty, expected_data_ty);
-    assert_eq!(ndim, 3);
-    let bound0 = reader.read_u64::<LittleEndian>()
-      .ok().expect("failed to deserialize!") as usize;
-    let bound1 = reader.read_u64::<LittleEndian>()
-      .ok().expect("failed to deserialize!") as usize;
-    let bound2 = reader.read_u64::<LittleEndian>()
-      .ok().expect("failed to deserialize!") as usize;
-    let dims = (bound0, bound1, bound2);
-    let mut arr = unsafe { Array3d::new(dims) };
-    {
-      let mut data_bytes = unsafe { from_raw_parts_mut(arr.data.as_mut_ptr() as *mut u8, size_of::<T>() * arr.data.len()) };
-      let mut read_idx: usize = 0;
-      loop {
-        match reader.read(&mut data_bytes[read_idx ..]) {
-          Ok(n) => {
-            read_idx += n;
-            if n == 0 {
-              break;
-            }
-          }
-          Err(e) => panic!("failed to deserialize: {:?}", e),
-        }
-      }
-      assert_eq!(read_idx, data_bytes.len());
+impl<'a, T> Array<'a, T, (usize, usize, usize)> for AlignedArray3d<T> where T: 'a + Copy {
+  type View     = Array3dView<'a, T>;
+  type ViewMut  = Array3dViewMut<'a, T>;
+
+  fn as_view(&'a self) -> Array3dView<'a, T> {
+    Array3dView{
+      data:     self.as_slice(),
+      bound:    self.bound,
+      stride:   self.stride,
     }
-    Ok(arr)
   }
 
-  fn serialize(&self, writer: &mut Write) -> Result<(), ()> {
-    let ty_id = T::serial_id();
-    writer.write_u32::<LittleEndian>(0x0000444e | ((ty_id as u32) << 24))
-      .ok().expect("failed to serialize!");
-    writer.write_u32::<LittleEndian>(3)
-      .ok().expect("failed to serialize!");
-    let (bound0, bound1, bound2) = self.bound;
-    writer.write_u64::<LittleEndian>(bound0 as u64)
-      .ok().expect("failed to serialize!");
-    writer.write_u64::<LittleEndian>(bound1 as u64)
-      .ok().expect("failed to serialize!");
-    writer.write_u64::<LittleEndian>(bound2 as u64)
-      .ok().expect("failed to serialize!");
-    if self.bound.to_least_stride() == self.stride {
-      let bytes = unsafe { from_raw_parts(self.data.as_ptr() as *const u8, size_of::<T>() * self.data.len()) };
-      writer.write_all(bytes)
-        .ok().expect("failed to serialize!");
-    } else {
-      unimplemented!();
+  fn as_view_mut(&'a mut self) -> Array3dViewMut<'a, T> {
+    let bound = self.bound;
+    let stride = self.stride;
+    Array3dViewMut{
+      data:     self.as_mut_slice(),
+      bound:    bound,
+      stride:   stride,
     }
-    Ok(())
   }
 }
 
@@ -705,17 +3505,90 @@ impl<'a, T> ArrayView<'a, T, (usize, usize, usize)> for Array3dView<'a, T> where
     self.stride
   }
 
-  fn len(&self) -> usize {
-    self.bound.len()
-  }
-
   unsafe fn as_ptr(&self) -> *const T {
     self.data.as_ptr()
   }
 
   fn view(self, lo: (usize, usize, usize), hi: (usize, usize, usize)) -> Array3dView<'a, T> {
-    // TODO(20151215)
-    unimplemented!();
+    assert_view_range(lo, hi, self.bound);
+    let new_bound = (hi.0 - lo.0, hi.1 - lo.1, hi.2 - lo.2);
+    let (new_offset, new_offset_end) = if new_bound.len() == 0 {
+      (0, 0)
+    } else {
+      (lo.offset(self.stride), (hi.0 - 1, hi.1 - 1, hi.2 - 1).offset(self.stride) + 1)
+    };
+    Array3dView{
+      data:     &self.data[new_offset .. new_offset_end],
+      bound:    new_bound,
+      stride:   self.stride,
+    }
+  }
+}
+
+impl<'a, T> Array3dView<'a, T> where T: 'a + Copy {
+  // Like `view`, but reports an out-of-range region instead of panicking.
+  pub fn try_view(self, lo: (usize, usize, usize), hi: (usize, usize, usize)) -> Result<Array3dView<'a, T>, ViewError<(usize, usize, usize)>> {
+    check_view_range(lo, hi, self.bound)?;
+    Ok(self.view(lo, hi))
+  }
+
+  pub fn to_owned(&self) -> Array3d<T> {
+    let mut array = unsafe { Array3d::new(self.bound) };
+    self.copy_into_slice(&mut array.data);
+    array
+  }
+
+  // Copies the logical elements, first axis fastest, into `dst` without
+  // any stride padding.
+  pub fn copy_into_slice(&self, dst: &mut [T]) {
+    assert_eq!(dst.len(), self.bound.len());
+    strided_copy_3d(self.data, self.stride, dst, self.bound.to_least_stride(), self.bound);
+  }
+
+  // Bounds-checked access; panics when `idx` is outside the view.
+  pub fn at(&self, idx: (usize, usize, usize)) -> &T {
+    match idx.checked_offset(self.bound, self.stride) {
+      Some(offset) => &self.data[offset],
+      None => panic!("index out of bounds: {:?} for bound {:?}", idx, self.bound),
+    }
+  }
+
+  pub fn contains(&self, idx: (usize, usize, usize)) -> bool {
+    idx.checked_offset(self.bound, self.stride).is_some()
+  }
+
+  pub fn ndim(&self) -> usize {
+    self.bound.ndim()
+  }
+
+  pub fn dim(&self, axis: usize) -> usize {
+    self.bound.dim(axis)
+  }
+}
+
+pub fn serialize_view<'a, T>(view: &Array3dView<'a, T>, writer: &mut dyn Write) -> Result<(), ArrayIoError> where T: 'a + SerialDataType + Copy {
+  let ty_id = T::serial_id();
+  let (bound0, bound1, bound2) = view.bound;
+  write_nd_header(writer, ty_id, &[bound0, bound1, bound2])?;
+  write_lanes_le(view.data, view.bound, view.stride, writer)
+}
+
+// Wraps a borrowed, contiguous buffer as a view without copying.
+pub fn view_from_slice<'a, T>(data: &'a [T], bound: (usize, usize, usize)) -> Array3dView<'a, T> where T: 'a + Copy {
+  assert_eq!(data.len(), bound.len());
+  Array3dView{
+    data:     data,
+    bound:    bound,
+    stride:   bound.to_least_stride(),
+  }
+}
+
+pub fn view_mut_from_slice<'a, T>(data: &'a mut [T], bound: (usize, usize, usize)) -> Array3dViewMut<'a, T> where T: 'a + Copy {
+  assert_eq!(data.len(), bound.len());
+  Array3dViewMut{
+    data:     data,
+    bound:    bound,
+    stride:   bound.to_least_stride(),
   }
 }
 
@@ -732,13 +3605,48 @@ impl<'a, T> Array3dViewMut<'a, T> where T: 'a + Copy {
 
   pub fn copy_from(&mut self, src: &Array3dView<'a, T>) {
     assert_eq!(self.bound(), src.bound());
-    if self.stride() == self.bound().to_least_stride() && self.stride() == src.stride() {
-      self.data.clone_from_slice(src.data);
-    } else {
-      // FIXME(20160202)
-      panic!("unimplemented: strided 3d array copy");
+    strided_copy_3d(src.data, src.stride, self.data, self.stride, self.bound);
+  }
+
+  // Like `view_mut`, but reports an out-of-range region instead of panicking.
+  pub fn try_view_mut(self, lo: (usize, usize, usize), hi: (usize, usize, usize)) -> Result<Array3dViewMut<'a, T>, ViewError<(usize, usize, usize)>> {
+    check_view_range(lo, hi, self.bound)?;
+    Ok(self.view_mut(lo, hi))
+  }
+
+  // Writes `f(idx)` at each position of the view, where `idx` is relative
+  // to the view's origin.
+  pub fn fill_from_fn<F>(&mut self, mut f: F) where F: FnMut((usize, usize, usize)) -> T {
+    let bound = self.bound;
+    let stride = self.stride;
+    for k in 0 .. bound.2 {
+      for j in 0 .. bound.1 {
+        for i in 0 .. bound.0 {
+          self.data[(i, j, k).offset(stride)] = f((i, j, k));
+        }
+      }
+    }
+  }
+
+  // Bounds-checked access; panics when `idx` is outside the view.
+  pub fn at(&mut self, idx: (usize, usize, usize)) -> &mut T {
+    match idx.checked_offset(self.bound, self.stride) {
+      Some(offset) => &mut self.data[offset],
+      None => panic!("index out of bounds: {:?} for bound {:?}", idx, self.bound),
     }
   }
+
+  pub fn contains(&self, idx: (usize, usize, usize)) -> bool {
+    idx.checked_offset(self.bound, self.stride).is_some()
+  }
+
+  pub fn ndim(&self) -> usize {
+    self.bound.ndim()
+  }
+
+  pub fn dim(&self, axis: usize) -> usize {
+    self.bound.dim(axis)
+  }
 }
 
 impl<'a, T> ArrayViewMut<'a, T, (usize, usize, usize)> for Array3dViewMut<'a, T> where T: 'a + Copy {
@@ -750,10 +3658,6 @@ impl<'a, T> ArrayViewMut<'a, T, (usize, usize, usize)> for Array3dViewMut<'a, T>
     self.stride
   }
 
-  fn len(&self) -> usize {
-    self.bound.len()
-  }
-
   unsafe fn as_ptr(&self) -> *const T {
     self.data.as_ptr()
   }
@@ -763,15 +3667,13 @@ impl<'a, T> ArrayViewMut<'a, T, (usize, usize, usize)> for Array3dViewMut<'a, T>
   }
 
   fn view_mut(self, lo: (usize, usize, usize), hi: (usize, usize, usize)) -> Array3dViewMut<'a, T> {
+    assert_view_range(lo, hi, self.bound);
     let new_bound = (hi.0 - lo.0, hi.1 - lo.1, hi.2 - lo.2);
-    let new_offset = lo.offset(self.stride);
-    // FIXME(20160203): array index arithmetic.
-    //let new_offset_end = hi.offset(self.stride);
-    assert_eq!(self.stride, self.bound.to_least_stride());
-    let new_offset_end = new_offset + new_bound.len();
-    assert!(new_offset <= self.data.len());
-    assert!(new_offset_end <= self.data.len());
-    assert!(new_offset <= new_offset_end);
+    let (new_offset, new_offset_end) = if new_bound.len() == 0 {
+      (0, 0)
+    } else {
+      (lo.offset(self.stride), (hi.0 - 1, hi.1 - 1, hi.2 - 1).offset(self.stride) + 1)
+    };
     Array3dViewMut{
       data:     &mut self.data[new_offset .. new_offset_end],
       bound:    new_bound,
@@ -779,3 +3681,244 @@ impl<'a, T> ArrayViewMut<'a, T, (usize, usize, usize)> for Array3dViewMut<'a, T>
     }
   }
 }
+
+pub struct ArrayNd<T> where T: Copy {
+  data:     Vec<T>,
+  bound:    Vec<usize>,
+  // Unlike the fixed-rank arrays, `stride[d]` is the element step along axis
+  // `d`, so `stride[0] == 1` for a contiguous array.
+  stride:   Vec<usize>,
+}
+
+fn nd_least_stride(bound: &[usize]) -> Vec<usize> {
+  let mut stride = Vec::with_capacity(bound.len());
+  let mut s = 1;
+  for &dim in bound.iter() {
+    stride.push(s);
+    s *= dim;
+  }
+  stride
+}
+
+fn nd_len(bound: &[usize]) -> usize {
+  bound.iter().product()
+}
+
+fn nd_offset(idx: &[usize], stride: &[usize]) -> usize {
+  assert_eq!(idx.len(), stride.len());
+  idx.iter().zip(stride.iter()).map(|(&i, &s)| i * s).sum()
+}
+
+impl<T> ArrayNd<T> where T: Copy {
+  pub unsafe fn new(bound: Vec<usize>) -> ArrayNd<T> {
+    let len = nd_len(&bound);
+    let data = alloc_uninit(len);
+    let stride = nd_least_stride(&bound);
+    ArrayNd{
+      data:     data,
+      bound:    bound,
+      stride:   stride,
+    }
+  }
+
+  pub fn with_data(data: Vec<T>, bound: Vec<usize>) -> ArrayNd<T> {
+    let len = nd_len(&bound);
+    assert_eq!(len, data.len());
+    let stride = nd_least_stride(&bound);
+    ArrayNd{
+      data:     data,
+      bound:    bound,
+      stride:   stride,
+    }
+  }
+
+  pub fn zeros(bound: Vec<usize>) -> ArrayNd<T> where T: Zero {
+    let len = nd_len(&bound);
+    let data = vec![T::zero(); len];
+    ArrayNd::with_data(data, bound)
+  }
+
+  pub fn as_slice(&self) -> &[T] {
+    &self.data
+  }
+
+  pub fn as_mut_slice(&mut self) -> &mut [T] {
+    &mut self.data
+  }
+
+  pub fn ndim(&self) -> usize {
+    self.bound.len()
+  }
+
+  pub fn bound(&self) -> &[usize] {
+    &self.bound
+  }
+
+  pub fn stride(&self) -> &[usize] {
+    &self.stride
+  }
+
+  pub fn len(&self) -> usize {
+    nd_len(&self.bound)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    nd_len(&self.bound) == 0
+  }
+
+  pub fn memory_bytes(&self) -> usize {
+    self.data.capacity() * size_of::<T>()
+  }
+
+  pub fn offset(&self, idx: &[usize]) -> usize {
+    nd_offset(idx, &self.stride)
+  }
+
+  pub fn is_contiguous(&self) -> bool {
+    self.stride == nd_least_stride(&self.bound)
+  }
+
+  pub fn reshape(self, new_bound: Vec<usize>) -> ArrayNd<T> {
+    assert!(self.is_contiguous());
+    assert_eq!(self.len(), nd_len(&new_bound));
+    ArrayNd::with_data(self.data, new_bound)
+  }
+
+  pub fn as_view<'a>(&'a self) -> ArrayNdView<'a, T> {
+    ArrayNdView{
+      data:     &self.data,
+      bound:    self.bound.clone(),
+      stride:   self.stride.clone(),
+    }
+  }
+
+  pub fn view<'a>(&'a self, lo: &[usize], hi: &[usize]) -> ArrayNdView<'a, T> {
+    self.as_view().view(lo, hi)
+  }
+}
+
+impl<T> ArrayNd<T> where T: SerialDataType + Copy {
+  pub fn serial_size(bound: &[usize]) -> usize {
+    8 + 8 * bound.len() + size_of::<T>() * nd_len(bound)
+  }
+
+  pub fn deserialize(reader: &mut dyn Read) -> Result<ArrayNd<T>, ArrayIoError> {
+    let header = read_nd_header(reader)?;
+    if header.data_ty != T::serial_id() {
+      return Err(ArrayIoError::DataTypeMismatch);
+    }
+    if checked_payload_size(size_of::<T>(), &header.dims).is_none() {
+      return Err(ArrayIoError::TooLarge);
+    }
+    let dims = header.dims;
+    let mut arr = unsafe { ArrayNd::new(dims) };
+    {
+      let data_bytes = unsafe { from_raw_parts_mut(arr.data.as_mut_ptr() as *mut u8, size_of::<T>() * arr.data.len()) };
+      read_payload_bytes(reader, data_bytes)?;
+    }
+    Ok(arr)
+  }
+
+  pub fn serialize(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    let ty_id = T::serial_id();
+    write_nd_header(writer, ty_id, &self.bound)?;
+    if self.is_contiguous() {
+      write_elems_le(&self.data, writer)?;
+    } else {
+      let mut compact = unsafe { alloc_uninit(self.len()) };
+      self.as_view().copy_into_slice(&mut compact);
+      write_elems_le(&compact, writer)?;
+    }
+    Ok(())
+  }
+}
+
+pub struct ArrayNdView<'a, T> where T: 'a + Copy {
+  data:     &'a [T],
+  bound:    Vec<usize>,
+  stride:   Vec<usize>,
+}
+
+impl<'a, T> ArrayNdView<'a, T> where T: 'a + Copy {
+  pub fn ndim(&self) -> usize {
+    self.bound.len()
+  }
+
+  pub fn bound(&self) -> &[usize] {
+    &self.bound
+  }
+
+  pub fn stride(&self) -> &[usize] {
+    &self.stride
+  }
+
+  pub fn len(&self) -> usize {
+    nd_len(&self.bound)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    nd_len(&self.bound) == 0
+  }
+
+  pub fn offset(&self, idx: &[usize]) -> usize {
+    nd_offset(idx, &self.stride)
+  }
+
+  pub fn is_contiguous(&self) -> bool {
+    self.stride == nd_least_stride(&self.bound)
+  }
+
+  // Copies the logical elements, first axis fastest, into `dst` without
+  // any stride padding.
+  pub fn copy_into_slice(&self, dst: &mut [T]) {
+    let ndim = self.bound.len();
+    let len = nd_len(&self.bound);
+    assert_eq!(dst.len(), len);
+    let mut idx = vec![0; ndim];
+    for x in dst.iter_mut() {
+      *x = self.data[nd_offset(&idx, &self.stride)];
+      for (i, &b) in idx.iter_mut().zip(self.bound.iter()) {
+        *i += 1;
+        if *i < b {
+          break;
+        }
+        *i = 0;
+      }
+    }
+  }
+
+  pub fn get(&self, idx: &[usize]) -> T {
+    for (&i, &b) in idx.iter().zip(self.bound.iter()) {
+      assert!(i < b);
+    }
+    self.data[self.offset(idx)]
+  }
+
+  pub fn view(self, lo: &[usize], hi: &[usize]) -> ArrayNdView<'a, T> {
+    let ndim = self.bound.len();
+    assert_eq!(lo.len(), ndim);
+    assert_eq!(hi.len(), ndim);
+    let mut new_bound = Vec::with_capacity(ndim);
+    for d in 0 .. ndim {
+      assert!(lo[d] <= hi[d]);
+      assert!(hi[d] <= self.bound[d]);
+      new_bound.push(hi[d] - lo[d]);
+    }
+    if nd_len(&new_bound) == 0 {
+      return ArrayNdView{
+        data:     &self.data[ .. 0],
+        bound:    new_bound,
+        stride:   self.stride,
+      };
+    }
+    let new_offset = nd_offset(lo, &self.stride);
+    let last: Vec<usize> = (0 .. ndim).map(|d| hi[d] - 1).collect();
+    let new_offset_end = nd_offset(&last, &self.stride) + 1;
+    assert!(new_offset_end <= self.data.len());
+    ArrayNdView{
+      data:     &self.data[new_offset .. new_offset_end],
+      bound:    new_bound,
+      stride:   self.stride,
+    }
+  }
+}
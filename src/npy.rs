@@ -0,0 +1,271 @@
+use super::{Shape, Array2d, Array3d, ArrayIoError};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+
+use std::io::{Read, Write, Result as IoResult};
+
+pub trait NpyDataType: Copy {
+  fn npy_descr() -> &'static str;
+  fn read_npy_elem(reader: &mut dyn Read) -> IoResult<Self>;
+  fn write_npy_elem(self, writer: &mut dyn Write) -> IoResult<()>;
+}
+
+impl NpyDataType for u8 {
+  fn npy_descr() -> &'static str { "|u1" }
+
+  fn read_npy_elem(reader: &mut dyn Read) -> IoResult<u8> {
+    reader.read_u8()
+  }
+
+  fn write_npy_elem(self, writer: &mut dyn Write) -> IoResult<()> {
+    writer.write_u8(self)
+  }
+}
+
+impl NpyDataType for i32 {
+  fn npy_descr() -> &'static str { "<i4" }
+
+  fn read_npy_elem(reader: &mut dyn Read) -> IoResult<i32> {
+    reader.read_i32::<LittleEndian>()
+  }
+
+  fn write_npy_elem(self, writer: &mut dyn Write) -> IoResult<()> {
+    writer.write_i32::<LittleEndian>(self)
+  }
+}
+
+impl NpyDataType for f32 {
+  fn npy_descr() -> &'static str { "<f4" }
+
+  fn read_npy_elem(reader: &mut dyn Read) -> IoResult<f32> {
+    reader.read_f32::<LittleEndian>()
+  }
+
+  fn write_npy_elem(self, writer: &mut dyn Write) -> IoResult<()> {
+    writer.write_f32::<LittleEndian>(self)
+  }
+}
+
+impl NpyDataType for f64 {
+  fn npy_descr() -> &'static str { "<f8" }
+
+  fn read_npy_elem(reader: &mut dyn Read) -> IoResult<f64> {
+    reader.read_f64::<LittleEndian>()
+  }
+
+  fn write_npy_elem(self, writer: &mut dyn Write) -> IoResult<()> {
+    writer.write_f64::<LittleEndian>(self)
+  }
+}
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+
+struct NpyHeader {
+  descr:          String,
+  fortran_order:  bool,
+  shape:          Vec<usize>,
+}
+
+fn descr_matches(descr: &str, expected: &str) -> bool {
+  // Single-byte types may be written as either "|" or "<".
+  (descr.starts_with("<") || descr.starts_with("|"))
+      && descr.len() == expected.len()
+      && descr[1 ..] == expected[1 ..]
+}
+
+fn parse_dict_value<'h>(header: &'h str, key: &str) -> Result<&'h str, ArrayIoError> {
+  let key_pos = match header.find(key) {
+    Some(pos) => pos + key.len(),
+    None => return Err(ArrayIoError::BadHeader),
+  };
+  let rest = &header[key_pos ..];
+  match rest.find(':') {
+    Some(pos) => Ok(rest[pos + 1 ..].trim_start()),
+    None => Err(ArrayIoError::BadHeader),
+  }
+}
+
+fn read_npy_header(reader: &mut dyn Read) -> Result<NpyHeader, ArrayIoError> {
+  let mut magic = [0u8; 6];
+  reader.read_exact(&mut magic)?;
+  if &magic[..] != NPY_MAGIC {
+    return Err(ArrayIoError::BadMagic);
+  }
+  let major = reader.read_u8()?;
+  let _minor = reader.read_u8()?;
+  if major != 1 {
+    return Err(ArrayIoError::BadVersion);
+  }
+  let header_len = reader.read_u16::<LittleEndian>()? as usize;
+  let mut header_bytes = vec![0u8; header_len];
+  reader.read_exact(&mut header_bytes)?;
+  let header = match String::from_utf8(header_bytes) {
+    Ok(header) => header,
+    Err(_) => return Err(ArrayIoError::BadHeader),
+  };
+
+  let descr_value = parse_dict_value(&header, "'descr'")?;
+  if !descr_value.starts_with("'") {
+    return Err(ArrayIoError::BadHeader);
+  }
+  let descr = match descr_value[1 ..].find('\'') {
+    Some(end) => descr_value[1 .. end + 1].to_string(),
+    None => return Err(ArrayIoError::BadHeader),
+  };
+
+  let fortran_value = parse_dict_value(&header, "'fortran_order'")?;
+  let fortran_order = if fortran_value.starts_with("True") {
+    true
+  } else if fortran_value.starts_with("False") {
+    false
+  } else {
+    return Err(ArrayIoError::BadHeader);
+  };
+
+  let shape_value = parse_dict_value(&header, "'shape'")?;
+  if !shape_value.starts_with("(") {
+    return Err(ArrayIoError::BadHeader);
+  }
+  let shape_str = match shape_value.find(')') {
+    Some(end) => &shape_value[1 .. end],
+    None => return Err(ArrayIoError::BadHeader),
+  };
+  let mut shape = vec![];
+  for dim_str in shape_str.split(',') {
+    let dim_str = dim_str.trim();
+    if dim_str.is_empty() {
+      continue;
+    }
+    match dim_str.trim_end_matches('L').parse::<usize>() {
+      Ok(dim) => shape.push(dim),
+      Err(_) => return Err(ArrayIoError::BadHeader),
+    }
+  }
+
+  Ok(NpyHeader{
+    descr:          descr,
+    fortran_order:  fortran_order,
+    shape:          shape,
+  })
+}
+
+fn write_npy_header(writer: &mut dyn Write, descr: &str, shape: &[usize]) -> Result<(), ArrayIoError> {
+  let shape_strs: Vec<String> = shape.iter().map(|d| d.to_string()).collect();
+  let shape_str = if shape.len() == 1 {
+    format!("({},)", shape_strs[0])
+  } else {
+    format!("({})", shape_strs.join(", "))
+  };
+  // Our layout has the first axis fastest varying, i.e. Fortran order.
+  let mut header = format!("{{'descr': '{}', 'fortran_order': True, 'shape': {}, }}", descr, shape_str);
+  // Pad with spaces and a trailing newline so the payload is 64-byte aligned.
+  let preamble_len = NPY_MAGIC.len() + 2 + 2;
+  let total_len = (preamble_len + header.len() + 1).div_ceil(64) * 64;
+  while preamble_len + header.len() + 1 < total_len {
+    header.push(' ');
+  }
+  header.push('\n');
+  writer.write_all(NPY_MAGIC)?;
+  writer.write_u8(1)?;
+  writer.write_u8(0)?;
+  writer.write_u16::<LittleEndian>(header.len() as u16)?;
+  writer.write_all(header.as_bytes())?;
+  Ok(())
+}
+
+fn read_npy_data<T>(reader: &mut dyn Read, header: &NpyHeader) -> Result<Vec<T>, ArrayIoError> where T: NpyDataType {
+  if !descr_matches(&header.descr, T::npy_descr()) {
+    return Err(ArrayIoError::DataTypeMismatch);
+  }
+  let shape = &header.shape;
+  let ndim = shape.len();
+  let mut len: usize = 1;
+  for &dim in shape.iter() {
+    len = match len.checked_mul(dim) {
+      Some(len) => len,
+      None => return Err(ArrayIoError::BadHeader),
+    };
+  }
+  // The buffer grows as elements are read, so a bogus shape runs out of
+  // input instead of sizing the allocation.
+  let mut elems = Vec::new();
+  for _ in 0 .. len {
+    elems.push(T::read_npy_elem(reader)?);
+  }
+  // Fortran order already matches our layout (first axis fastest varying);
+  // C order is permuted after reading.
+  if header.fortran_order {
+    return Ok(elems);
+  }
+  let mut least_stride = vec![1; ndim];
+  for d in 1 .. ndim {
+    least_stride[d] = least_stride[d - 1] * shape[d - 1];
+  }
+  let mut data = elems.clone();
+  let mut idx = vec![0; ndim];
+  for &elem in elems.iter() {
+    let offset: usize = idx.iter().zip(least_stride.iter()).map(|(&i, &s)| i * s).sum();
+    data[offset] = elem;
+    for d in (0 .. ndim).rev() {
+      idx[d] += 1;
+      if idx[d] < shape[d] {
+        break;
+      }
+      idx[d] = 0;
+    }
+  }
+  Ok(data)
+}
+
+impl<T> Array2d<T> where T: NpyDataType {
+  pub fn to_npy(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    write_npy_header(writer, T::npy_descr(), &[self.bound.0, self.bound.1])?;
+    for j in 0 .. self.bound.1 {
+      for i in 0 .. self.bound.0 {
+        self.data[(i, j).offset(self.stride)].write_npy_elem(writer)?;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn from_npy(reader: &mut dyn Read) -> Result<Array2d<T>, ArrayIoError> {
+    let header = read_npy_header(reader)?;
+    if header.shape.len() != 2 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    let bound = (header.shape[0], header.shape[1]);
+    let data = read_npy_data(reader, &header)?;
+    Ok(Array2d{
+      data:     data,
+      bound:    bound,
+      stride:   bound.to_least_stride(),
+    })
+  }
+}
+
+impl<T> Array3d<T> where T: NpyDataType {
+  pub fn to_npy(&self, writer: &mut dyn Write) -> Result<(), ArrayIoError> {
+    let bound = self.bound();
+    let stride = self.stride();
+    write_npy_header(writer, T::npy_descr(), &[bound.0, bound.1, bound.2])?;
+    let data = self.as_slice();
+    for k in 0 .. bound.2 {
+      for j in 0 .. bound.1 {
+        for i in 0 .. bound.0 {
+          data[(i, j, k).offset(stride)].write_npy_elem(writer)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  pub fn from_npy(reader: &mut dyn Read) -> Result<Array3d<T>, ArrayIoError> {
+    let header = read_npy_header(reader)?;
+    if header.shape.len() != 3 {
+      return Err(ArrayIoError::NdimMismatch);
+    }
+    let bound = (header.shape[0], header.shape[1], header.shape[2]);
+    let data = read_npy_data(reader, &header)?;
+    Ok(Array3d::with_data(data, bound))
+  }
+}
@@ -0,0 +1,1981 @@
+use super::*;
+
+#[test]
+fn full_view_is_contiguous_and_strided_view_is_not() {
+  let mut arr: Array3d<f32> = Array3d::zeros((4, 5, 6));
+  assert!(arr.as_view().is_contiguous());
+  assert!(arr.as_view_mut().is_contiguous());
+  assert!(!arr.as_view_mut().view_mut((1, 0, 0), (3, 5, 6)).is_contiguous());
+  assert!(!arr.as_view_mut().view_mut((0, 1, 1), (4, 4, 5)).is_contiguous());
+}
+
+#[test]
+fn to_u8_saturating_clamps() {
+  let arr = Array3d::with_data(vec![-3.0, 0.4, 1.0, 100.0, 300.0, f32::NAN], (6, 1, 1));
+  let bytes = arr.to_u8_saturating(2.0);
+  assert_eq!(bytes.as_slice(), &[0, 1, 2, 200, 255, 0]);
+}
+
+fn npy_bytes(descr: &str, fortran_order: bool, shape: &str, payload: &[u8]) -> Vec<u8> {
+  let mut header = format!("{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}",
+      descr, if fortran_order { "True" } else { "False" }, shape);
+  while (10 + header.len() + 1) % 64 != 0 {
+    header.push(' ');
+  }
+  header.push('\n');
+  let mut buf = b"\x93NUMPY\x01\x00".to_vec();
+  buf.write_u16::<LittleEndian>(header.len() as u16).unwrap();
+  buf.extend_from_slice(header.as_bytes());
+  buf.extend_from_slice(payload);
+  buf
+}
+
+#[test]
+fn from_npy_reads_hand_built_files() {
+  // C order (2, 3): [[0, 1, 2], [3, 4, 5]].
+  let buf = npy_bytes("|u1", false, "(2, 3)", &[0, 1, 2, 3, 4, 5]);
+  let arr = Array2d::<u8>::from_npy(&mut &buf[..]).unwrap();
+  assert_eq!(arr.as_slice(), &[0, 3, 1, 4, 2, 5]);
+  let buf = npy_bytes("|u1", true, "(2, 3)", &[0, 1, 2, 3, 4, 5]);
+  let arr = Array2d::<u8>::from_npy(&mut &buf[..]).unwrap();
+  assert_eq!(arr.as_slice(), &[0, 1, 2, 3, 4, 5]);
+  let mut payload = vec![];
+  for x in 0 .. 8 {
+    payload.write_i32::<LittleEndian>(x).unwrap();
+  }
+  let buf = npy_bytes("<i4", false, "(2, 2, 2)", &payload);
+  let arr = Array3d::<i32>::from_npy(&mut &buf[..]).unwrap();
+  assert_eq!(arr.as_slice(), &[0, 4, 2, 6, 1, 5, 3, 7]);
+  match Array3d::<f32>::from_npy(&mut &buf[..]) {
+    Err(ArrayIoError::DataTypeMismatch) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn from_npy_rejects_overflowing_shape() {
+  let buf = npy_bytes("<f4", true, "(4294967296, 4294967296, 2)", &[]);
+  match Array3d::<f32>::from_npy(&mut &buf[..]) {
+    Err(ArrayIoError::BadHeader) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn from_npy_truncated_huge_shape_is_io_error() {
+  let buf = npy_bytes("<f4", false, "(1048576, 1048576)", &[0, 0, 0, 0]);
+  match Array2d::<f32>::from_npy(&mut &buf[..]) {
+    Err(ArrayIoError::Io(_)) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn to_npy_round_trips_through_from_npy() {
+  let arr = Array2d{
+    data:     vec![1.5f32, 0.0, -2.0, 4.0, 3.25, -8.5],
+    bound:    (2, 3),
+    stride:   2,
+  };
+  let mut buf = vec![];
+  arr.to_npy(&mut buf).unwrap();
+  let arr2 = Array2d::<f32>::from_npy(&mut &buf[..]).unwrap();
+  assert_eq!(arr2.bound, (2, 3));
+  assert_eq!(arr2.as_slice(), arr.as_slice());
+
+  let arr = Array3d::with_data((0 .. 24).collect::<Vec<i32>>(), (2, 3, 4));
+  let mut buf = vec![];
+  arr.to_npy(&mut buf).unwrap();
+  let arr2 = Array3d::<i32>::from_npy(&mut &buf[..]).unwrap();
+  assert_eq!(arr2.bound(), (2, 3, 4));
+  assert_eq!(arr2.as_slice(), arr.as_slice());
+}
+
+#[test]
+fn array_nd_round_trips_1d_to_4d() {
+  let bounds: Vec<Vec<usize>> = vec![vec![5], vec![2, 3], vec![2, 3, 4], vec![2, 1, 3, 2]];
+  for bound in bounds.into_iter() {
+    let len = nd_len(&bound);
+    let arr = ArrayNd::with_data((0 .. len).map(|x| x as f32 * 0.5).collect(), bound.clone());
+    let mut buf = vec![];
+    arr.serialize(&mut buf).unwrap();
+    assert_eq!(buf.len(), ArrayNd::<f32>::serial_size(&bound));
+    let decoded = ArrayNd::<f32>::deserialize(&mut &buf[..]).unwrap();
+    assert_eq!(decoded.ndim(), bound.len());
+    assert_eq!(decoded.bound(), &bound[..]);
+    assert_eq!(decoded.as_slice(), arr.as_slice());
+  }
+}
+
+#[test]
+fn array_nd_deserialize_rejects_wrong_dtype() {
+  let arr = ArrayNd::<u8>::zeros(vec![2, 2]);
+  let mut buf = vec![];
+  arr.serialize(&mut buf).unwrap();
+  match ArrayNd::<f32>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::DataTypeMismatch) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn array_nd_deserialize_rejects_overflowing_bound() {
+  let mut buf = vec![];
+  buf.write_u32::<LittleEndian>(0x0100444e).unwrap();
+  buf.write_u32::<LittleEndian>(2).unwrap();
+  buf.write_u64::<LittleEndian>(1 << 62).unwrap();
+  buf.write_u64::<LittleEndian>(1 << 62).unwrap();
+  match ArrayNd::<f32>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::TooLarge) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn swap_changes_only_the_two_positions() {
+  let mut arr = Array3d::with_data((0 .. 24).collect::<Vec<i32>>(), (2, 3, 4));
+  arr.swap((0, 1, 2), (1, 2, 3));
+  // The two positions sit at offsets 14 and 23.
+  for (p, &x) in arr.as_slice().iter().enumerate() {
+    let expected = match p {
+      14 => 23,
+      23 => 14,
+      p => p as i32,
+    };
+    assert_eq!(x, expected);
+  }
+
+  let mut arr = Array2d{
+    data:     vec![1, 4, 2, 5, 3, 6],
+    bound:    (2, 3),
+    stride:   2,
+  };
+  arr.swap((0, 0), (1, 2));
+  assert_eq!(arr.as_slice(), &[6, 4, 2, 5, 3, 1]);
+}
+
+#[test]
+fn f32_to_bit_array_matches_from_byte_array() {
+  let bound = (5, 7, 3);
+  let values: Vec<f32> = (0 .. bound.len()).map(|x| ((x * 37) % 11) as f32 - 5.0).collect();
+  let bytes: Vec<u8> = values.iter().map(|&x| if x > 0.5 { 1 } else { 0 }).collect();
+  let arr = Array3d::with_data(values, bound);
+  let bits = arr.to_bit_array(0.5);
+  let expected = BitArray3d::from_byte_array(&Array3d::with_data(bytes, bound));
+  assert_eq!(bits.bound(), expected.bound());
+  assert_eq!(bits.data, expected.data);
+  assert_eq!(bits.into_bytes(1).as_slice(), expected.into_bytes(1).as_slice());
+}
+
+fn rle_stream(bound: (usize, usize, usize), payload_len: u64, payload: &[u8]) -> Vec<u8> {
+  let mut buf = vec![];
+  buf.write_u32::<LittleEndian>(0xfe00444e).unwrap();
+  buf.write_u32::<LittleEndian>(3).unwrap();
+  buf.write_u64::<LittleEndian>(bound.0 as u64).unwrap();
+  buf.write_u64::<LittleEndian>(bound.1 as u64).unwrap();
+  buf.write_u64::<LittleEndian>(bound.2 as u64).unwrap();
+  buf.write_u64::<LittleEndian>(payload_len).unwrap();
+  buf.extend_from_slice(payload);
+  buf
+}
+
+fn sparse_mask() -> BitArray3d {
+  let mut bytes = Array3d::with_data(vec![0u8; 1024], (16, 16, 4));
+  // (1, 2, 0), (2, 2, 0), (3, 2, 0) and (15, 15, 3).
+  for &p in [33, 34, 35, 1023].iter() {
+    bytes.as_mut_slice()[p] = 1;
+  }
+  bytes.to_bit_array()
+}
+
+#[test]
+fn rle_round_trip() {
+  let mask = sparse_mask();
+  let mut buf = vec![];
+  mask.serialize_rle(&mut buf).unwrap();
+  assert_eq!(buf[3], 254);
+  let decoded = BitArray3d::deserialize_rle(&mut &buf[..]).unwrap();
+  assert_eq!(decoded.bound(), mask.bound());
+  assert_eq!(decoded.data, mask.data);
+}
+
+#[test]
+fn rle_is_smaller_than_raw_for_sparse_masks() {
+  let mask = sparse_mask();
+  let mut rle = vec![];
+  mask.serialize_rle(&mut rle).unwrap();
+  let mut raw = vec![];
+  mask.serialize(&mut raw).unwrap();
+  assert!(rle.len() < raw.len());
+}
+
+#[test]
+fn rle_falls_back_to_raw_for_dense_noise() {
+  let checkerboard: Vec<u8> = (0 .. 64).map(|p| ((p % 8 + p / 8) % 2) as u8).collect();
+  let mask = Array3d::with_data(checkerboard, (8, 8, 1)).to_bit_array();
+  let mut buf = vec![];
+  mask.serialize_rle(&mut buf).unwrap();
+  assert_eq!(buf[3], 255);
+  let decoded = BitArray3d::deserialize_rle(&mut &buf[..]).unwrap();
+  assert_eq!(decoded.data, mask.data);
+}
+
+#[test]
+fn rle_rejects_malformed_streams() {
+  // The last varint byte has its continuation bit set.
+  let buf = rle_stream((4, 1, 1), 2, &[4, 0x80]);
+  match BitArray3d::deserialize_rle(&mut &buf[..]) {
+    Err(ArrayIoError::BadHeader) => {}
+    _ => panic!(),
+  }
+  // The payload is shorter than its declared length.
+  let buf = rle_stream((4, 1, 1), 2, &[4]);
+  match BitArray3d::deserialize_rle(&mut &buf[..]) {
+    Err(ArrayIoError::Io(_)) => {}
+    _ => panic!(),
+  }
+  // A run overshoots the bound.
+  let buf = rle_stream((4, 1, 1), 2, &[2, 3]);
+  match BitArray3d::deserialize_rle(&mut &buf[..]) {
+    Err(ArrayIoError::BadHeader) => {}
+    _ => panic!(),
+  }
+  // The runs stop short of the bound.
+  let buf = rle_stream((4, 1, 1), 2, &[1, 1]);
+  match BitArray3d::deserialize_rle(&mut &buf[..]) {
+    Err(ArrayIoError::BadHeader) => {}
+    _ => panic!(),
+  }
+  // A varint longer than a u64 allows.
+  let buf = rle_stream((4, 1, 1), 11, &[0xff; 11]);
+  match BitArray3d::deserialize_rle(&mut &buf[..]) {
+    Err(ArrayIoError::BadHeader) => {}
+    _ => panic!(),
+  }
+  // A declared payload length larger than the raw encoding.
+  let buf = rle_stream((4, 1, 1), 1 << 40, &[]);
+  match BitArray3d::deserialize_rle(&mut &buf[..]) {
+    Err(ArrayIoError::BadHeader) => {}
+    _ => panic!(),
+  }
+  // A bound whose size overflows.
+  let buf = rle_stream((1 << 32, 1 << 32, 2), 0, &[]);
+  match BitArray3d::deserialize_rle(&mut &buf[..]) {
+    Err(ArrayIoError::TooLarge) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn view_as_row_major_reads_c_order() {
+  // Read as row-major, the buffer `0 .. 24` holds a (2, 3, 4) array whose
+  // element `[i][j][k]` is `12*i + 4*j + k`.
+  let arr = Array3d::with_data((0 .. 24).collect::<Vec<i32>>(), (2, 3, 4));
+  let view = arr.view_as_row_major();
+  assert_eq!(view.bound(), (4, 3, 2));
+  for i in 0 .. 2 {
+    for j in 0 .. 3 {
+      for k in 0 .. 4 {
+        let x = view.data[(k, j, i).offset(view.stride())];
+        assert_eq!(x, (12 * i + 4 * j + k) as i32);
+      }
+    }
+  }
+  // The column-major layout of the same buffer orders the elements
+  // differently.
+  assert_eq!(arr.as_slice()[(1, 0, 0).offset(arr.stride())], 1);
+  assert_eq!(view.data[(0, 0, 1).offset(view.stride())], 12);
+}
+
+#[test]
+fn checked_offset_rejects_out_of_bounds() {
+  assert_eq!(2usize.checked_offset(3, ()), Some(2));
+  assert_eq!(3usize.checked_offset(3, ()), None);
+  assert_eq!((2, 3).checked_offset((3, 4), 5), Some(2 + 3 * 5));
+  assert_eq!((3, 0).checked_offset((3, 4), 5), None);
+  assert_eq!((0, 4).checked_offset((3, 4), 5), None);
+  assert_eq!((1, 2, 3).checked_offset((2, 3, 4), (2, 3)), Some(1 + 2 * 2 + 3 * 3 * 2));
+  assert_eq!((1, 2, 4).checked_offset((2, 3, 4), (2, 3)), None);
+
+  let arr = Array2d{
+    data:     vec![0, 1, 2, 3, 4, 5],
+    bound:    (2, 3),
+    stride:   2,
+  };
+  assert_eq!(arr.get((1, 2)), Some(&5));
+  assert_eq!(arr.get((2, 0)), None);
+}
+
+#[test]
+fn row_and_col_extract_lanes() {
+  // Rows [0, 1, 2, 3], [4, 5, 6, 7] and [8, 9, 10, 11].
+  let arr = Array2d{
+    data:     vec![0, 4, 8, 1, 5, 9, 2, 6, 10, 3, 7, 11],
+    bound:    (3, 4),
+    stride:   3,
+  };
+  assert_eq!(arr.row(1).as_slice(), &[4, 5, 6, 7]);
+  assert_eq!(arr.col(2).as_slice(), &[2, 6, 10]);
+}
+
+#[test]
+fn diagonal_of_square_and_wide_arrays() {
+  // Rows [1, 2, 3], [4, 5, 6] and [7, 8, 9].
+  let mut arr = Array2d{
+    data:     vec![1, 4, 7, 2, 5, 8, 3, 6, 9],
+    bound:    (3, 3),
+    stride:   3,
+  };
+  assert_eq!(arr.diagonal().as_slice(), &[1, 5, 9]);
+  arr.set_diagonal(&[0, 0, 0]);
+  assert_eq!(arr.as_slice(), &[0, 4, 7, 2, 0, 8, 3, 6, 0]);
+  // Rows [1, 2, 3, 4] and [5, 6, 7, 8].
+  let arr = Array2d{
+    data:     vec![1, 5, 2, 6, 3, 7, 4, 8],
+    bound:    (2, 4),
+    stride:   2,
+  };
+  assert_eq!(arr.diagonal().as_slice(), &[1, 6]);
+}
+
+#[test]
+fn into_slices_axis2_and_stack_axis2_round_trip() {
+  let arr = Array3d::with_data((0 .. 24).collect::<Vec<i32>>(), (2, 3, 4));
+  let slices = arr.into_slices_axis2();
+  assert_eq!(slices.len(), 4);
+  for (k, slice) in slices.iter().enumerate() {
+    assert_eq!(slice.bound, (2, 3));
+    assert_eq!(slice.as_slice(), &arr.as_slice()[6 * k .. 6 * (k + 1)]);
+  }
+  let stacked = Array3d::stack_axis2(&slices);
+  assert_eq!(stacked.bound(), (2, 3, 4));
+  assert_eq!(stacked.as_slice(), arr.as_slice());
+}
+
+#[test]
+fn view_to_owned_copies_the_region() {
+  let arr = Array3d::with_data((0 .. 120).collect::<Vec<i32>>(), (4, 5, 6));
+  let region = arr.as_view().view((1, 2, 3), (3, 5, 4)).to_owned();
+  assert_eq!(region.bound(), (2, 3, 1));
+  // Element (i, j, 0) of the region is element (i + 1, j + 2, 3) of `arr`,
+  // at offset (i + 1) + 4 * (j + 2) + 20 * 3.
+  assert_eq!(region.as_slice(), &[69, 70, 73, 74, 77, 78]);
+
+  let arr = Array2d{
+    data:     (0 .. 12).collect::<Vec<i32>>(),
+    bound:    (3, 4),
+    stride:   3,
+  };
+  let region = arr.as_view().view((1, 1), (3, 3)).to_owned();
+  assert_eq!(region.bound, (2, 2));
+  assert_eq!(region.as_slice(), &[4, 5, 7, 8]);
+}
+
+#[test]
+fn major_iter_offsets_are_increasing() {
+  let bound = (2, 3, 2);
+  let stride = bound.to_least_stride();
+  let offsets: Vec<usize> = bound.major_iter().map(|idx| idx.offset(stride)).collect();
+  assert_eq!(offsets.len(), bound.len());
+  assert_eq!(offsets, (0 .. 12).collect::<Vec<_>>());
+  assert_eq!((0, 3, 1).major_iter().count(), 0);
+}
+
+#[test]
+fn reinterpret_cast_round_trips_bytes() {
+  // Eight rows of three bytes each, so each column of 8 bytes becomes two
+  // u32 words.
+  let bytes: Vec<u8> = (0 .. 24).collect();
+  let arr = Array2d{
+    data:     bytes.clone(),
+    bound:    (8, 3),
+    stride:   8,
+  };
+  let words = match arr.reinterpret_cast::<u32>() {
+    Ok(words) => words,
+    Err(_) => panic!(),
+  };
+  assert_eq!(words.as_view().bound(), (2, 3));
+  let back = match words.reinterpret_cast::<u8>() {
+    Ok(back) => back,
+    Err(_) => panic!(),
+  };
+  assert_eq!(back.bound, (8, 3));
+  assert_eq!(back.as_slice(), &bytes[..]);
+  let arr = Array2d{
+    data:     vec![0u8; 3],
+    bound:    (3, 1),
+    stride:   3,
+  };
+  match arr.reinterpret_cast::<u32>() {
+    Err(CastError::SizeMismatch) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn cumsum_axis_computes_prefix_sums() {
+  let mut arr = Array3d::with_data(vec![1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0], (3, 2, 1));
+  arr.cumsum_axis(0);
+  assert_eq!(arr.as_slice(), &[1.0, 3.0, 6.0, 4.0, 9.0, 15.0]);
+  let mut arr = Array3d::with_data(vec![1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0], (3, 2, 1));
+  arr.cumsum_axis(1);
+  assert_eq!(arr.as_slice(), &[1.0, 2.0, 3.0, 5.0, 7.0, 9.0]);
+}
+
+#[test]
+fn flip_and_rotate_2x3() {
+  // Rows [1, 2, 3] and [4, 5, 6].
+  let arr = Array2d{
+    data:     vec![1, 4, 2, 5, 3, 6],
+    bound:    (2, 3),
+    stride:   2,
+  };
+  // Rows [3, 2, 1] and [6, 5, 4].
+  assert_eq!(arr.flip_horizontal().as_slice(), &[3, 6, 2, 5, 1, 4]);
+  // Rows [4, 5, 6] and [1, 2, 3].
+  assert_eq!(arr.flip_vertical().as_slice(), &[4, 1, 5, 2, 6, 3]);
+  // Rows [3, 6], [2, 5] and [1, 4].
+  let rot = arr.rotate90();
+  assert_eq!(rot.bound, (3, 2));
+  assert_eq!(rot.as_slice(), &[3, 2, 1, 6, 5, 4]);
+}
+
+#[test]
+fn fold_computes_product() {
+  let arr = Array2d{
+    data:     vec![1, 4, 2, 5, 3, 6],
+    bound:    (2, 3),
+    stride:   2,
+  };
+  assert_eq!(arr.fold(1, |acc, x| acc * x), 720);
+  let arr = Array3d::with_data(vec![1, 2, 3, 4, 5, 6], (1, 2, 3));
+  assert_eq!(arr.fold(1, |acc, x| acc * x), 720);
+  assert_eq!(arr.fold(vec![], |mut acc, x| { acc.push(x); acc }), vec![1, 2, 3, 4, 5, 6]);
+}
+
+#[test]
+fn with_data_strided_reads_past_padding() {
+  // A (3, 2, 2) array with each row padded to 4 and each plane padded to 3
+  // rows; the padding holds -1.
+  let mut data = vec![-1; 4 * 3 * 2];
+  for k in 0 .. 2 {
+    for j in 0 .. 2 {
+      for i in 0 .. 3 {
+        data[i + 4 * j + 12 * k] = (i + 10 * j + 100 * k) as i32;
+      }
+    }
+  }
+  let arr = Array3d::with_data_strided(data, (3, 2, 2), (4, 3));
+  for k in 0 .. 2 {
+    for j in 0 .. 2 {
+      for i in 0 .. 3 {
+        assert_eq!(*arr.get((i, j, k)).unwrap(), (i + 10 * j + 100 * k) as i32);
+      }
+    }
+  }
+  assert!(arr.get((3, 0, 0)).is_none());
+  let compact = arr.as_view().to_owned();
+  assert_eq!(compact.as_slice(), &[0, 1, 2, 10, 11, 12, 100, 101, 102, 110, 111, 112]);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn uninit_arrays_are_poisoned_in_debug() {
+  let arr: Array3d<u8> = unsafe { Array3d::new((3, 4, 5)) };
+  assert!(arr.as_slice().iter().all(|&x| x == UNINIT_POISON_BYTE));
+  let arr: Array2d<f32> = unsafe { Array2d::new((3, 4)) };
+  let poison = f32::from_bits(u32::from_le_bytes([UNINIT_POISON_BYTE; 4]));
+  assert!(arr.as_slice().iter().all(|&x| x.to_bits() == poison.to_bits()));
+}
+
+#[test]
+fn from_elem_fills_every_element() {
+  let arr = Array2d::from_elem((3, 4), 7u8);
+  assert_eq!(arr.as_slice(), &[7; 12][..]);
+  let arr = Array3d::from_elem((2, 3, 4), -1.5f32);
+  assert_eq!(arr.bound(), (2, 3, 4));
+  assert!(arr.as_slice().iter().all(|&x| x == -1.5));
+}
+
+#[test]
+fn ndim_and_dim_report_the_shape() {
+  let arr: Array3d<f32> = Array3d::zeros((2, 3, 4));
+  assert_eq!(arr.ndim(), 3);
+  assert_eq!(arr.dim(0), 2);
+  assert_eq!(arr.dim(1), 3);
+  assert_eq!(arr.dim(2), 4);
+  assert_eq!(arr.as_view().dim(1), 3);
+  let arr: Array2d<f32> = Array2d::zeros((2, 3));
+  assert_eq!(arr.ndim(), 2);
+  assert_eq!(arr.dim(1), 3);
+}
+
+#[test]
+fn rows_mut_normalizes_each_row() {
+  // Rows [1, 2, 1] and [3, 2, 5].
+  let mut arr = Array2d{
+    data:     vec![1.0f32, 3.0, 2.0, 2.0, 1.0, 5.0],
+    bound:    (2, 3),
+    stride:   2,
+  };
+  for mut row in arr.rows_mut() {
+    let sum: f32 = row.iter().map(|x| **x).sum();
+    for x in row.iter_mut() {
+      **x /= sum;
+    }
+  }
+  // Rows [0.25, 0.5, 0.25] and [0.3, 0.2, 0.5].
+  assert_eq!(arr.as_slice(), &[0.25, 0.3, 0.5, 0.2, 0.25, 0.5]);
+  assert_eq!(arr.rows_mut().count(), 2);
+}
+
+#[test]
+fn cols_mut_normalizes_each_column() {
+  // Rows [1, 2, 0.5] and [3, 2, 1.5].
+  let mut arr = Array2d{
+    data:     vec![1.0f32, 3.0, 2.0, 2.0, 0.5, 1.5],
+    bound:    (2, 3),
+    stride:   2,
+  };
+  for col in arr.cols_mut() {
+    let sum: f32 = col.iter().sum();
+    for x in col.iter_mut() {
+      *x /= sum;
+    }
+  }
+  assert_eq!(arr.as_slice(), &[0.25, 0.75, 0.5, 0.5, 0.25, 0.75]);
+  assert_eq!(arr.cols_mut().count(), 3);
+}
+
+#[test]
+fn gather_axis0_picks_rows() {
+  // Rows [1, 2], [3, 4] and [5, 6].
+  let arr = Array2d{
+    data:     vec![1, 3, 5, 2, 4, 6],
+    bound:    (3, 2),
+    stride:   3,
+  };
+  let gathered = arr.gather_axis0(&[2, 0, 2]);
+  // Rows [5, 6], [1, 2] and [5, 6].
+  assert_eq!(gathered.bound, (3, 2));
+  assert_eq!(gathered.as_slice(), &[5, 1, 5, 6, 2, 6]);
+}
+
+#[test]
+fn scatter_add_axis0_accumulates_duplicates() {
+  let mut arr: Array2d<f32> = Array2d::zeros((3, 2));
+  // Rows [1, 2], [10, 20] and [100, 200].
+  let src = Array2d{
+    data:     vec![1.0, 10.0, 100.0, 2.0, 20.0, 200.0],
+    bound:    (3, 2),
+    stride:   3,
+  };
+  arr.scatter_add_axis0(&[1, 1, 0], &src);
+  // Rows [100, 200], [11, 22] and [0, 0].
+  assert_eq!(arr.as_slice(), &[100.0, 11.0, 0.0, 200.0, 22.0, 0.0]);
+}
+
+#[test]
+fn raw_parts_round_trip() {
+  let arr = Array3d::with_data_strided((0 .. 24).collect::<Vec<i32>>(), (3, 2, 3), (4, 2));
+  let (data, bound, stride) = arr.into_raw_parts();
+  assert_eq!(data, (0 .. 24).collect::<Vec<i32>>());
+  assert_eq!(bound, (3, 2, 3));
+  assert_eq!(stride, (4, 2));
+  let arr = unsafe { Array3d::from_raw_parts(data.clone(), bound, stride) };
+  assert_eq!((arr.bound(), arr.stride()), (bound, stride));
+  assert_eq!(arr.as_slice(), &data[..]);
+  let arr = Array3d::from_raw_parts_checked(data.clone(), bound, stride).unwrap();
+  assert_eq!((arr.bound(), arr.stride()), (bound, stride));
+  assert!(Array3d::from_raw_parts_checked(data, (3, 2, 4), stride).is_none());
+}
+
+#[test]
+fn push_row_builds_an_array() {
+  let mut arr: Array2d<i32> = Array2d::zeros((0, 2));
+  arr.push_row(&[1, 2]);
+  arr.push_row(&[3, 4]);
+  arr.push_row(&[5, 6]);
+  assert_eq!(arr.as_view().bound(), (3, 2));
+  // Rows [1, 2], [3, 4] and [5, 6].
+  assert_eq!(arr.as_slice(), &[1, 3, 5, 2, 4, 6]);
+  arr.push_col(&[7, 8, 9]);
+  assert_eq!(arr.as_view().bound(), (3, 3));
+  assert_eq!(arr.as_slice(), &[1, 3, 5, 2, 4, 6, 7, 8, 9]);
+}
+
+#[test]
+fn nd_header_round_trips() {
+  for dims in [vec![3, 4], vec![2, 3, 5]].iter() {
+    let mut buf = vec![];
+    write_nd_header(&mut buf, 1, dims).unwrap();
+    assert_eq!(buf.len(), 8 + 8 * dims.len());
+    let header = read_nd_header(&mut &buf[..]).unwrap();
+    assert_eq!(header.data_ty, 1);
+    assert_eq!(&header.dims, dims);
+  }
+}
+
+struct FailingWriter;
+
+impl Write for FailingWriter {
+  fn write(&mut self, _buf: &[u8]) -> ::std::io::Result<usize> {
+    Err(IoError::other("write failed"))
+  }
+
+  fn flush(&mut self) -> ::std::io::Result<()> {
+    Ok(())
+  }
+}
+
+#[test]
+fn serialize_view_writes_strided_region() {
+  let arr = Array3d::with_data((0 .. 120).map(|x| x as f32).collect(), (4, 5, 6));
+  let view = arr.as_view().view((1, 1, 2), (3, 4, 5));
+  let mut buf = vec![];
+  serialize_view(&view, &mut buf).unwrap();
+  assert_eq!(buf.len(), 8 + 3 * 8 + 2 * 3 * 3 * size_of::<f32>());
+  let back = <Array3d<f32> as NdArraySerialize<f32, (usize, usize, usize)>>::deserialize(&mut &buf[..]).unwrap();
+  let expected = view.to_owned();
+  assert_eq!(back.bound(), expected.bound());
+  assert_eq!(back.as_slice(), expected.as_slice());
+}
+
+#[test]
+fn serialize_view_of_empty_strided_view_writes_only_the_header() {
+  let arr = Array3d::with_data((0 .. 24).map(|x| x as f32).collect(), (2, 3, 4));
+  let view = arr.as_view().view((0, 0, 0), (0, 2, 2));
+  let mut buf = vec![];
+  serialize_view(&view, &mut buf).unwrap();
+  assert_eq!(buf.len(), 8 + 3 * 8);
+  let back = <Array3d<f32> as NdArraySerialize<f32, (usize, usize, usize)>>::deserialize(&mut &buf[..]).unwrap();
+  assert_eq!(back.bound(), (0, 2, 2));
+}
+
+#[test]
+fn serialize_view_propagates_write_errors() {
+  let arr = Array3d::with_data((0 .. 24).map(|x| x as f32).collect(), (2, 3, 4));
+  match serialize_view(&arr.as_view(), &mut FailingWriter) {
+    Err(ArrayIoError::Io(_)) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn to_interleaved_rgb_emits_pixels_in_scanline_order() {
+  // Channel `c` of pixel `(x, y)` is `100*c + 10*y + x`.
+  let arr: Array3d<u8> = Array3d::with_data(vec![
+    0, 1, 10, 11,
+    100, 101, 110, 111,
+    200, 201, 210, 211,
+  ], (2, 2, 3));
+  assert_eq!(arr.to_interleaved_rgb(), vec![
+    0, 100, 200,  1, 101, 201,
+    10, 110, 210,  11, 111, 211,
+  ]);
+}
+
+#[test]
+fn div_inplace_quotient_and_divide_by_zero() {
+  let mut a = Array3d::with_data(vec![6.0f32, -3.0, 1.0, 0.0], (4, 1, 1));
+  a.div_inplace(&Array3d::with_data(vec![2.0, 4.0, 0.0, 0.0], (4, 1, 1)));
+  assert_eq!(a.as_slice()[0], 3.0);
+  assert_eq!(a.as_slice()[1], -0.75);
+  assert_eq!(a.as_slice()[2], f32::INFINITY);
+  assert!(a.as_slice()[3].is_nan());
+  let mut a = Array2d{data: vec![1.0f32, -1.0], bound: (1, 2), stride: 1};
+  a.div_inplace(&Array2d{data: vec![0.5, 0.0], bound: (1, 2), stride: 1});
+  assert_eq!(a.as_slice(), &[2.0, f32::NEG_INFINITY][..]);
+}
+
+#[test]
+fn recip_inplace_respects_stride() {
+  // Only the first two entries of each column are in bounds.
+  let mut a = Array2d{data: vec![2.0f32, 0.0, 7.0, -4.0, 0.5, 7.0], bound: (2, 2), stride: 3};
+  a.recip_inplace();
+  assert_eq!(a.data, vec![0.5, f32::INFINITY, 7.0, -0.25, 2.0, 7.0]);
+}
+
+#[test]
+fn elementwise_math_matches_scalar_functions() {
+  let xs = vec![0.25f32, 0.5, 1.0, 2.0, 3.5, 7.0];
+  let check = |f: &dyn Fn(&mut Array3d<f32>), g: &dyn Fn(f32) -> f32| {
+    let mut arr = Array3d::with_data(xs.clone(), (3, 2, 1));
+    f(&mut arr);
+    for (&y, &x) in arr.as_slice().iter().zip(xs.iter()) {
+      assert!((y - g(x)).abs() <= 1.0e-6 * g(x).abs().max(1.0));
+    }
+  };
+  check(&|a| a.exp_inplace(), &|x| x.exp());
+  check(&|a| a.ln_inplace(), &|x| x.ln());
+  check(&|a| a.sqrt_inplace(), &|x| x.sqrt());
+  check(&|a| a.tanh_inplace(), &|x| x.tanh());
+  check(&|a| a.recip_inplace(), &|x| 1.0 / x);
+  let mut arr = Array2d{data: xs.clone(), bound: (1, 6), stride: 1};
+  arr.exp_inplace();
+  for (&y, &x) in arr.as_slice().iter().zip(xs.iter()) {
+    assert!((y - x.exp()).abs() <= 1.0e-6 * x.exp());
+  }
+}
+
+#[test]
+fn softmax_axis_normalizes_lanes() {
+  // Rows are `[1, 2, 3]` and `[-1, 0, 1000]`.
+  let data = vec![1.0f32, -1.0, 2.0, 0.0, 3.0, 1000.0];
+  for &axis in [0, 1].iter() {
+    let mut arr = Array2d{data: data.clone(), bound: (2, 3), stride: 2};
+    arr.softmax_axis(axis);
+    let x = |i: usize, j: usize| arr.data[i + 2 * j];
+    if axis == 1 {
+      for i in 0 .. 2 {
+        assert!((x(i, 0) + x(i, 1) + x(i, 2) - 1.0).abs() < 1.0e-6);
+      }
+    } else {
+      for j in 0 .. 3 {
+        assert!((x(0, j) + x(1, j) - 1.0).abs() < 1.0e-6);
+      }
+    }
+  }
+  // softmax([0, ln 3]) = [1/4, 3/4].
+  let mut arr = Array2d{data: vec![0.0f32, 3.0f32.ln()], bound: (1, 2), stride: 1};
+  arr.softmax_axis(1);
+  assert!((arr.data[0] - 0.25).abs() < 1.0e-6);
+  assert!((arr.data[1] - 0.75).abs() < 1.0e-6);
+}
+
+#[test]
+fn argmax_axis_over_last_axis() {
+  // The lanes along axis 2 are `[1, 5, 2]`, `[7, 0, -1]`, `[0, 0, 3]` and
+  // `[4, 4, 1]` at `(0, 0)`, `(1, 0)`, `(0, 1)` and `(1, 1)`.
+  let arr = Array3d::with_data(vec![
+    1.0f32, 7.0, 0.0, 4.0,
+    5.0, 0.0, 0.0, 4.0,
+    2.0, -1.0, 3.0, 1.0,
+  ], (2, 2, 3));
+  let argmax = arr.argmax_axis(2);
+  assert_eq!(argmax.bound, (2, 2));
+  // Ties keep the first index.
+  assert_eq!(argmax.as_slice(), &[1, 0, 2, 0][..]);
+}
+
+#[test]
+fn hash_ignores_stride_padding() {
+  use std::collections::HashMap;
+  use std::collections::hash_map::DefaultHasher;
+
+  let contiguous = Array3d::with_data((0 .. 12).collect::<Vec<i32>>(), (2, 3, 2));
+  let mut data = vec![-1; 3 * 4 * 2];
+  for k in 0 .. 2 {
+    for j in 0 .. 3 {
+      for i in 0 .. 2 {
+        data[i + 3 * j + 12 * k] = *contiguous.get((i, j, k)).unwrap();
+      }
+    }
+  }
+  let strided = Array3d::with_data_strided(data, (2, 3, 2), (3, 4));
+  assert!(strided == contiguous);
+  let hash = |arr: &Array3d<i32>| {
+    let mut hasher = DefaultHasher::new();
+    arr.hash(&mut hasher);
+    hasher.finish()
+  };
+  assert_eq!(hash(&strided), hash(&contiguous));
+  let mut map = HashMap::new();
+  map.insert(contiguous, "iota");
+  assert_eq!(map.get(&strided), Some(&"iota"));
+}
+
+#[test]
+fn serialize_canonical_writes_little_endian_bytes() {
+  let arr = Array3d::with_data(vec![1.0f32, -2.0], (2, 1, 1));
+  let mut buf = vec![];
+  arr.serialize_canonical(&mut buf).unwrap();
+  assert_eq!(buf, vec![
+      b'N', b'D', 0, 1,
+      3, 0, 0, 0,
+      2, 0, 0, 0, 0, 0, 0, 0,
+      1, 0, 0, 0, 0, 0, 0, 0,
+      1, 0, 0, 0, 0, 0, 0, 0,
+      0x00, 0x00, 0x80, 0x3f,
+      0x00, 0x00, 0x00, 0xc0,
+  ]);
+  let decoded = Array3d::<f32>::deserialize_canonical(&mut &buf[..]).unwrap();
+  assert_eq!(decoded.as_slice(), arr.as_slice());
+}
+
+#[test]
+fn deserialize_canonical_reports_errors() {
+  let arr = Array3d::<u8>::from_elem((2, 2, 1), 3);
+  let mut buf = vec![];
+  arr.serialize_canonical(&mut buf).unwrap();
+  match Array3d::<f32>::deserialize_canonical(&mut &buf[..]) {
+    Err(ArrayIoError::DataTypeMismatch) => {}
+    _ => panic!(),
+  }
+  buf.pop();
+  match Array3d::<u8>::deserialize_canonical(&mut &buf[..]) {
+    Err(ArrayIoError::Io(_)) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn set_masked_changes_only_masked_positions() {
+  let bound = (3, 4, 2);
+  let mask_bytes: Vec<u8> = (0 .. bound.len()).map(|idx| if idx % 5 == 1 { 1 } else { 0 }).collect();
+  let mask = BitArray3d::from_byte_array(&Array3d::with_data(mask_bytes.clone(), bound));
+  let orig = Array3d::with_data((0 .. bound.len()).map(|x| x as f32).collect(), bound);
+  let mut arr = orig.clone();
+  arr.set_masked(&mask, -1.0);
+  for (idx, (&x, &y)) in arr.as_slice().iter().zip(orig.as_slice().iter()).enumerate() {
+    if mask_bytes[idx] != 0 {
+      assert_eq!(x, -1.0);
+    } else {
+      assert_eq!(x, y);
+    }
+  }
+}
+
+#[test]
+fn select_with_checkerboard_mask() {
+  let bound = (4, 3, 2);
+  // Elements are laid out with `.0` fastest, matching `i + 4*j + 12*k`.
+  let mask_bytes: Vec<u8> = (0 .. bound.len()).map(|idx| {
+    let (i, j, k) = (idx % 4, (idx / 4) % 3, idx / 12);
+    ((i + j + k) % 2) as u8
+  }).collect();
+  let mask = BitArray3d::from_byte_array(&Array3d::with_data(mask_bytes, bound));
+  let a = Array3d::with_data((0 .. bound.len() as i32).collect(), bound);
+  let b = Array3d::with_data((0 .. bound.len() as i32).map(|x| -x).collect(), bound);
+  let selected = Array3d::select(&mask, &a, &b);
+  for k in 0 .. 2 {
+    for j in 0 .. 3 {
+      for i in 0 .. 4 {
+        let x = *a.get((i, j, k)).unwrap();
+        let expected = if (i + j + k) % 2 == 1 { x } else { -x };
+        assert_eq!(*selected.get((i, j, k)).unwrap(), expected);
+      }
+    }
+  }
+}
+
+#[test]
+fn u8_saturating_arithmetic() {
+  let mut arr = Array3d::with_data(vec![0u8, 100, 200, 255], (4, 1, 1));
+  arr.saturating_add_inplace(&Array3d::with_data(vec![10, 100, 100, 1], (4, 1, 1)));
+  assert_eq!(arr.as_slice(), &[10, 200, 255, 255]);
+  arr.saturating_sub_inplace(&Array3d::with_data(vec![20, 50, 0, 255], (4, 1, 1)));
+  assert_eq!(arr.as_slice(), &[0, 150, 255, 0]);
+}
+
+#[test]
+fn read_nd_header_rejects_huge_ndim() {
+  let mut buf = vec![b'N', b'D', 0, 1];
+  buf.write_u32::<LittleEndian>(0xffff_ffff).unwrap();
+  match read_nd_header(&mut &buf[..]) {
+    Err(ArrayIoError::BadHeader) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn deserialize_bounded_rejects_10gb_header() {
+  let mut buf = vec![];
+  // 10 * 2^28 f32 elements, i.e. 10 GB, with no payload behind it.
+  write_nd_header(&mut buf, 1, &[1 << 20, 10 << 8, 1]).unwrap();
+  match Array3d::<f32>::deserialize_bounded(&mut &buf[..], 1 << 30) {
+    Err(ArrayIoError::TooLarge) => {}
+    _ => panic!(),
+  }
+  let mut buf = vec![];
+  write_nd_header(&mut buf, 0, &[10 << 30, 1]).unwrap();
+  match Array2d::<u8>::deserialize_bounded(&mut &buf[..], 1 << 30) {
+    Err(ArrayIoError::TooLarge) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn deserialize_bounded_accepts_arrays_within_limit() {
+  let arr = Array3d::with_data(vec![1.0f32, 2.0, 3.0, 4.0], (2, 2, 1));
+  let mut buf = vec![];
+  write_nd_header(&mut buf, 1, &[2, 2, 1]).unwrap();
+  for &x in arr.as_slice().iter() {
+    buf.write_f32::<LittleEndian>(x).unwrap();
+  }
+  let back = Array3d::<f32>::deserialize_bounded(&mut &buf[..], buf.len()).unwrap();
+  assert_eq!(back.as_slice(), arr.as_slice());
+  match Array3d::<f32>::deserialize_bounded(&mut &buf[..], buf.len() - 1) {
+    Err(ArrayIoError::TooLarge) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn aligned_array_is_aligned() {
+  for &align in [16, 64, 4096].iter() {
+    let arr: AlignedArray3d<f32> = unsafe { AlignedArray3d::new((3, 5, 7), align) };
+    assert_eq!(arr.as_ptr() as usize % align, 0);
+    assert_eq!(arr.as_slice().len(), 3 * 5 * 7);
+  }
+}
+
+#[test]
+#[should_panic]
+fn aligned_array_size_overflow_panics() {
+  let _arr: AlignedArray3d<f32> = unsafe { AlignedArray3d::new((1 << 62, 1, 1), 64) };
+}
+
+#[test]
+#[should_panic(expected = "at (1, 2, 0): 5 != 7")]
+fn assert_array_eq_names_the_mismatch() {
+  let a = Array3d::with_data((0 .. 6).collect::<Vec<i32>>(), (2, 3, 1));
+  // Offset 5 is `(1, 2, 0)`.
+  let mut b = a.clone();
+  b.as_mut_slice()[5] = 7;
+  let c = Array2d{data: vec![1, 3, 2, 4], bound: (2, 2), stride: 2};
+  assert!(c.first_mismatch(&c).is_none());
+  assert_array_eq!(a, b);
+}
+
+#[test]
+fn strided_serialized_length_matches_serial_size() {
+  let bound = (3, 2, 2);
+  let arr = Array3d::with_data_strided(vec![0.0f32; 5 * 4 * 2], bound, (5, 4));
+  let mut buf = vec![];
+  serialize_view(&arr.as_view(), &mut buf).unwrap();
+  assert_eq!(buf.len(), <Array3d<f32> as NdArraySerialize<f32, (usize, usize, usize)>>::serial_size(bound));
+}
+
+#[test]
+fn view_len_is_bound_len() {
+  let mut arr: Array3d<f32> = Array3d::zeros((4, 5, 6));
+  let view = arr.as_view().view((1, 1, 1), (3, 4, 6));
+  assert_eq!(view.len(), (2, 3, 5).len());
+  assert_eq!(view.len(), 30);
+  let view = arr.as_view_mut().view_mut((0, 0, 0), (4, 5, 1));
+  assert_eq!(view.len(), 20);
+  let arr: Array2d<f32> = Array2d::zeros((3, 7));
+  assert_eq!(arr.as_view().view((1, 1), (3, 3)).len(), 4);
+}
+
+#[test]
+fn batches_axis0_yields_short_last_batch() {
+  // Row `i` is `[i, 10 + i]`.
+  let arr = Array2d{data: vec![0, 1, 2, 3, 4, 10, 11, 12, 13, 14], bound: (5, 2), stride: 5};
+  let batches: Vec<_> = arr.batches_axis0(2).collect();
+  let sizes: Vec<usize> = batches.iter().map(|b| b.bound().0).collect();
+  assert_eq!(sizes, vec![2, 2, 1]);
+  assert_eq!(batches[2].to_owned().as_slice(), &[4, 14][..]);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn shuffle_axis0_permutes_rows() {
+  use rand::{SeedableRng, XorShiftRng};
+
+  // Row `i` is `[i, 100 + i]`.
+  let mut arr = Array2d{data: (0 .. 10).chain(100 .. 110).collect::<Vec<i32>>(), bound: (10, 2), stride: 10};
+  let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+  arr.shuffle_axis0(&mut rng);
+  let order = vec![0, 8, 6, 7, 3, 9, 4, 2, 5, 1];
+  assert!(order != (0 .. 10).collect::<Vec<i32>>());
+  assert_eq!(&arr.data[.. 10], &order[..]);
+  // Rows move as a unit.
+  for i in 0 .. 10 {
+    assert_eq!(arr.data[10 + i], 100 + arr.data[i]);
+  }
+}
+
+#[test]
+fn flatten_channels_keeps_the_layout() {
+  let arr = Array3d::with_data((0 .. 12).collect::<Vec<i32>>(), (2, 2, 3));
+  let flat = arr.flatten_channels();
+  assert_eq!(flat.as_view().bound(), (4, 3));
+  assert_eq!(flat.as_slice(), arr.as_slice());
+  for c in 0 .. 3 {
+    for y in 0 .. 2 {
+      for x in 0 .. 2 {
+        assert_eq!(*flat.get((x + 2 * y, c)).unwrap(), *arr.get((x, y, c)).unwrap());
+      }
+    }
+  }
+}
+
+#[test]
+fn zero_row_array_round_trips() {
+  let arr: Array2d<f32> = Array2d::zeros((0, 3));
+  let mut buf = vec![];
+  arr.serialize(&mut buf).unwrap();
+  let back = <Array2d<f32> as NdArraySerialize<f32, (usize, usize)>>::deserialize(&mut &buf[..]).unwrap();
+  assert_eq!(back.as_view().bound(), (0, 3));
+  assert!(back == arr);
+  assert!(back.as_slice().is_empty());
+}
+
+#[test]
+fn fill_from_fn_writes_only_the_view() {
+  let mut arr: Array3d<i32> = Array3d::zeros((4, 4, 3));
+  arr.as_view_mut().view_mut((1, 1, 1), (3, 4, 2)).fill_from_fn(|(i, j, k)| (1 + i + 10 * j + 100 * k) as i32);
+  for k in 0 .. 3 {
+    for j in 0 .. 4 {
+      for i in 0 .. 4 {
+        let inside = (1 .. 3).contains(&i) && j >= 1 && k == 1;
+        let expected = if inside { (1 + (i - 1) + 10 * (j - 1)) as i32 } else { 0 };
+        assert_eq!(*arr.get((i, j, k)).unwrap(), expected);
+      }
+    }
+  }
+}
+
+#[test]
+fn data_type_id_matches_serial_id() {
+  let arr: Array3d<f32> = Array3d::zeros((2, 2, 2));
+  assert_eq!(arr.data_type_id(), 1);
+  let arr: Array2d<u8> = Array2d::zeros((2, 2));
+  assert_eq!(arr.data_type_id(), 0);
+}
+
+#[test]
+fn any_array_downcasts() {
+  let mut buf = vec![];
+  Array3d::with_data(vec![0.0f32, 1.0], (2, 1, 1)).serialize(&mut buf).unwrap();
+  let any = AnyArray3d::deserialize(&mut &buf[..]).unwrap();
+  assert!(any.as_f32().is_some());
+  assert!(any.as_u8().is_none());
+  assert_eq!(any.into_f32().unwrap().as_slice(), &[0.0, 1.0]);
+  let mut buf = vec![];
+  Array3d::with_data(vec![0u8, 1], (2, 1, 1)).serialize(&mut buf).unwrap();
+  let any = AnyArray3d::deserialize(&mut &buf[..]).unwrap();
+  assert!(any.as_f32().is_none());
+  assert!(any.into_f32().is_none());
+}
+
+#[test]
+fn memory_bytes_counts_the_buffer() {
+  let arr: Array3d<f32> = Array3d::zeros((2, 3, 4));
+  assert_eq!(arr.memory_bytes(), 24 * 4);
+  let arr: Array2d<u8> = Array2d::zeros((5, 3));
+  assert_eq!(arr.memory_bytes(), 15);
+  let arr = Array1d::with_data(vec![0u16; 7]);
+  assert_eq!(arr.memory_bytes(), 14);
+  let bits: BitArray3d = Array3d::<u8>::zeros((10, 10, 1)).to_bit_array();
+  assert_eq!(bits.memory_bytes(), 2 * 8);
+}
+
+#[test]
+fn copy_into_slice_from_strided_view() {
+  let arr = Array3d::with_data((0 .. 24).collect::<Vec<i32>>(), (4, 3, 2));
+  let view = arr.as_view().view((1, 1, 0), (3, 3, 2));
+  let mut dst = vec![0; view.len()];
+  view.copy_into_slice(&mut dst);
+  assert_eq!(dst, vec![5, 6, 9, 10, 17, 18, 21, 22]);
+}
+
+#[test]
+fn reshape_keeps_the_buffer_order() {
+  let arr = Array3d::with_data((0 .. 24).collect::<Vec<i32>>(), (2, 3, 4));
+  let reshaped = arr.try_reshape((4, 3, 2)).ok().unwrap();
+  assert_eq!(reshaped.bound(), (4, 3, 2));
+  assert_eq!(reshaped.as_slice(), arr.as_slice());
+  assert!(arr.clone().reshape((4, 3, 2)) == reshaped);
+  match arr.try_reshape((4, 3, 3)) {
+    Err(ReshapeError::LengthMismatch) => {}
+    _ => panic!(),
+  }
+  let strided = Array3d::with_data_strided(vec![0; 24], (2, 3, 2), (3, 4));
+  match strided.try_reshape((3, 2, 2)) {
+    Err(ReshapeError::NotContiguous) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn im2col_with_2x2_kernel_and_stride_2() {
+  // Element `(i, j)` is `4*i + j`.
+  let arr = Array2d{data: (0 .. 16).map(|p| 4 * (p % 4) + p / 4).collect::<Vec<i32>>(), bound: (4, 4), stride: 4};
+  let cols = arr.im2col((2, 2), (2, 2));
+  assert_eq!(cols.as_view().bound(), (4, 4));
+  // Each column is one window with axis 0 fastest; windows are ordered the
+  // same way.
+  assert_eq!(cols.as_slice(), &[
+    0, 4, 1, 5,
+    8, 12, 9, 13,
+    2, 6, 3, 7,
+    10, 14, 11, 15,
+  ]);
+  // The overhanging last row and column of windows are dropped.
+  let arr: Array2d<i32> = Array2d::zeros((5, 5));
+  assert_eq!(arr.im2col((2, 2), (2, 2)).as_view().bound(), (4, 4));
+}
+
+#[test]
+fn col2im_inverts_non_overlapping_im2col() {
+  let arr = Array2d{data: (0 .. 24).map(|x| x as f32).collect(), bound: (4, 6), stride: 4};
+  let cols = arr.im2col((2, 3), (2, 3));
+  let back = Array2d::col2im(&cols, (4, 6), (2, 3), (2, 3));
+  assert!(back == arr);
+  // Overlapping windows accumulate.
+  let ones: Array2d<f32> = Array2d::from_elem((3, 1), 1.0);
+  let cols = ones.im2col((2, 1), (1, 1));
+  assert_eq!(Array2d::col2im(&cols, (3, 1), (2, 1), (1, 1)).as_slice(), &[1.0, 2.0, 1.0][..]);
+}
+
+#[test]
+fn at_accumulates_in_place() {
+  let mut arr: Array3d<f32> = Array3d::zeros((2, 2, 2));
+  for n in 0 .. 5 {
+    *arr.at((1, 0, 1)) += n as f32;
+  }
+  *arr.at((0, 1, 0)) -= 2.0;
+  assert_eq!(*arr.get((1, 0, 1)).unwrap(), 10.0);
+  assert_eq!(*arr.get((0, 1, 0)).unwrap(), -2.0);
+  assert_eq!(arr.as_slice().iter().sum::<f32>(), 8.0);
+}
+
+#[test]
+#[should_panic]
+fn at_panics_out_of_bounds() {
+  let mut arr: Array3d<f32> = Array3d::zeros((2, 2, 2));
+  *arr.at((0, 2, 0)) = 1.0;
+}
+
+#[test]
+fn deserialize_empty_reader_is_empty() {
+  let buf: Vec<u8> = vec![];
+  match <Array2d<f32> as NdArraySerialize<f32, (usize, usize)>>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::Empty) => {}
+    _ => panic!(),
+  }
+  match <Array3d<f32> as NdArraySerialize<f32, (usize, usize, usize)>>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::Empty) => {}
+    _ => panic!(),
+  }
+  match BitArray3d::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::Empty) => {}
+    _ => panic!(),
+  }
+  match ArrayNd::<u8>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::Empty) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn bit_array_deserialize_rejects_other_dtype() {
+  let arr = Array3d::<u8>::from_elem((2, 2, 2), 1);
+  let mut buf = vec![];
+  arr.serialize(&mut buf).unwrap();
+  match BitArray3d::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::DataTypeMismatch) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn shape_to_vec_and_from_vec_round_trip() {
+  assert_eq!(7usize.to_vec(), vec![7]);
+  assert_eq!(<usize as Shape>::from_vec(&[7]), Some(7));
+  assert_eq!((2, 3).to_vec(), vec![2, 3]);
+  assert_eq!(<(usize, usize) as Shape>::from_vec(&[2, 3]), Some((2, 3)));
+  assert_eq!((2, 3, 4).to_vec(), vec![2, 3, 4]);
+  assert_eq!(<(usize, usize, usize) as Shape>::from_vec(&[2, 3, 4]), Some((2, 3, 4)));
+  assert_eq!(<(usize, usize) as Shape>::from_vec(&[2, 3, 4]), None);
+  assert_eq!(<(usize, usize, usize) as Shape>::from_vec(&[2, 3]), None);
+}
+
+#[test]
+fn interior_strips_the_border() {
+  let arr = Array3d::with_data((0 .. 64).collect::<Vec<i32>>(), (4, 4, 4));
+  let interior = arr.interior(1);
+  assert_eq!(interior.bound(), (2, 2, 2));
+  assert_eq!(interior.to_owned().as_slice(), &[21, 22, 25, 26, 37, 38, 41, 42][..]);
+  assert_eq!(arr.interior(2).bound(), (0, 0, 0));
+}
+
+#[test]
+fn pad_reflect_and_wrap_along_axis0() {
+  let arr = Array3d::with_data(vec![1, 2, 3, 4], (4, 1, 1));
+  let reflected = arr.pad_reflect((2, 0, 0));
+  assert_eq!(reflected.bound(), (8, 1, 1));
+  assert_eq!(reflected.as_slice(), &[3, 2, 1, 2, 3, 4, 3, 2]);
+  let wrapped = arr.pad_wrap((2, 0, 0));
+  assert_eq!(wrapped.as_slice(), &[3, 4, 1, 2, 3, 4, 1, 2]);
+  let wrapped = arr.pad_wrap((5, 0, 0));
+  assert_eq!(wrapped.as_slice(), &[4, 1, 2, 3, 4, 1, 2, 3, 4, 1, 2, 3, 4, 1]);
+}
+
+#[test]
+#[should_panic]
+fn pad_reflect_rejects_amount_past_the_edge() {
+  let arr = Array3d::with_data(vec![1, 2, 3, 4], (4, 1, 1));
+  arr.pad_reflect((4, 0, 0));
+}
+
+#[test]
+fn iota_counts_in_offset_order() {
+  let arr = Array3d::<f32>::iota((2, 2, 1));
+  assert_eq!(arr.as_slice(), &[0.0, 1.0, 2.0, 3.0]);
+  let arr = Array3d::<u8>::iota((4, 1, 1));
+  assert_eq!(arr.as_slice(), &[0, 1, 2, 3]);
+  // Exactly fills the range of u8 without overflowing.
+  let arr = Array3d::<u8>::iota((16, 16, 1));
+  assert_eq!(arr.as_slice()[255], 255);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn iota_past_type_range_panics_in_debug() {
+  let _arr = Array3d::<u8>::iota((300, 1, 1));
+}
+
+#[test]
+fn major_iter_visits_first_axis_fastest() {
+  let idxs: Vec<_> = (2, 3, 2).major_iter().collect();
+  assert_eq!(idxs, vec![
+    (0, 0, 0), (1, 0, 0), (0, 1, 0), (1, 1, 0), (0, 2, 0), (1, 2, 0),
+    (0, 0, 1), (1, 0, 1), (0, 1, 1), (1, 1, 1), (0, 2, 1), (1, 2, 1),
+  ]);
+  assert_eq!((0, 3, 2).major_iter().count(), 0);
+  assert_eq!((2, 3).major_iter().count(), 6);
+}
+
+#[test]
+fn contains_checks_each_axis() {
+  let arr: Array3d<f32> = Array3d::zeros((2, 3, 4));
+  assert!(arr.contains((0, 0, 0)));
+  assert!(arr.contains((1, 2, 3)));
+  assert!(!arr.contains((2, 0, 0)));
+  assert!(!arr.contains((0, 3, 0)));
+  assert!(!arr.contains((0, 0, 4)));
+  let arr: Array2d<f32> = Array2d::zeros((2, 3));
+  assert!(arr.contains((1, 2)));
+  assert!(!arr.contains((2, 2)));
+  assert!(!arr.contains((1, 3)));
+  assert!(arr.as_view().view((1, 1), (2, 3)).contains((0, 1)));
+  assert!(!arr.as_view().view((1, 1), (2, 3)).contains((1, 0)));
+}
+
+#[test]
+fn from_rows_indexes_row_then_column() {
+  let arr = Array2d::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6]]);
+  assert_eq!(arr.as_view().bound(), (2, 3));
+  assert_eq!(*arr.get((0, 2)).unwrap(), 3);
+  assert_eq!(*arr.get((1, 0)).unwrap(), 4);
+  assert_eq!(*arr.get((1, 2)).unwrap(), 6);
+  assert_eq!(arr.as_slice(), &[1, 4, 2, 5, 3, 6]);
+}
+
+#[test]
+#[should_panic]
+fn from_rows_rejects_ragged_rows() {
+  let _arr = Array2d::from_rows(vec![vec![1, 2, 3], vec![4, 5]]);
+}
+
+#[test]
+fn to_nested_vec_inverts_from_rows() {
+  let rows = vec![vec![1.0f32, 2.0, 3.0], vec![4.0, 5.0, 6.0]];
+  let arr = Array2d::from_rows(rows.clone());
+  assert_eq!(arr.to_nested_vec(), rows);
+  let strided = Array2d{data: vec![1.0f32, 4.0, 0.0, 2.0, 5.0, 0.0, 3.0, 6.0], bound: (2, 3), stride: 3};
+  assert_eq!(strided.to_nested_vec(), rows);
+}
+
+#[test]
+fn linear_index_is_column_major() {
+  let arr: Array2d<f32> = Array2d::zeros((3, 4));
+  for j in 0 .. 4 {
+    for i in 0 .. 3 {
+      assert_eq!(arr.linear_index((i, j)), i + 3 * j);
+    }
+  }
+  let arr: Array3d<f32> = Array3d::zeros((3, 4, 2));
+  assert_eq!(arr.linear_index((2, 3, 1)), 2 + 3 * 3 + 12);
+}
+
+#[test]
+fn iter_set_yields_nonzero_coords() {
+  let bound = (5, 7, 3);
+  let mut bytes: Array3d<u8> = Array3d::zeros(bound);
+  let mut expected = vec![];
+  for idx in bound.major_iter() {
+    if (idx.0 * 7 + idx.1 * 3 + idx.2) % 4 == 0 {
+      *bytes.at(idx) = 1;
+      expected.push(idx);
+    }
+  }
+  let bits = bytes.to_bit_array();
+  let set: Vec<_> = bits.iter_set().collect();
+  assert_eq!(set, expected);
+  assert_eq!(Array3d::<u8>::zeros((0, 2, 2)).to_bit_array().iter_set().count(), 0);
+}
+
+fn bit_array_with_padding_set(bound: (usize, usize, usize)) -> BitArray3d {
+  let raw_len = bound.len().div_ceil(64);
+  let mut buf = vec![];
+  write_nd_header(&mut buf, 255, &[bound.0, bound.1, bound.2]).unwrap();
+  for _ in 0 .. raw_len {
+    buf.write_u64::<LittleEndian>(!0).unwrap();
+  }
+  BitArray3d::deserialize(&mut &buf[..]).unwrap()
+}
+
+#[test]
+fn count_ones_ignores_padding_bits() {
+  let mask = bit_array_with_padding_set((3, 1, 1));
+  assert_eq!(mask.count_ones(), 3);
+  assert_eq!(mask.iter_set().count(), 3);
+  let mask = bit_array_with_padding_set((10, 7, 1));
+  assert_eq!(mask.count_ones(), 70);
+  assert_eq!(mask.iter_set().count(), 70);
+}
+
+#[test]
+fn crop_and_count_ones() {
+  let mut bytes = Array3d::<u8>::from_elem((4, 3, 2), 0);
+  for &idx in [(0, 0, 0), (1, 1, 0), (2, 1, 1), (3, 2, 1)].iter() {
+    *bytes.at(idx) = 1;
+  }
+  let mask = bytes.to_bit_array();
+  assert_eq!(mask.count_ones(), 4);
+  let cropped = mask.crop((1, 1, 0), (3, 3, 2));
+  assert_eq!(cropped.bound(), (2, 2, 2));
+  assert_eq!(cropped.count_ones(), 2);
+  assert_eq!(cropped.into_bytes(1).as_slice(), &[1, 0, 0, 0, 0, 1, 0, 0][..]);
+  assert_eq!(mask.crop((0, 0, 0), (0, 3, 2)).count_ones(), 0);
+}
+
+#[test]
+fn array2d_and_array3d_conversions() {
+  let arr2 = Array2d::from_rows(vec![vec![1, 2, 3], vec![4, 5, 6]]);
+  let arr3 = Array3d::from(arr2);
+  assert_eq!(arr3.bound(), (2, 3, 1));
+  assert_eq!(*arr3.get((1, 2, 0)).unwrap(), 6);
+  let arr2 = Array2d::try_from(arr3).ok().unwrap();
+  assert_eq!(arr2.to_nested_vec(), vec![vec![1, 2, 3], vec![4, 5, 6]]);
+  match Array2d::try_from(Array3d::<i32>::iota((2, 3, 2))) {
+    Err(arr) => assert_eq!(arr.bound(), (2, 3, 2)),
+    Ok(_) => panic!(),
+  }
+}
+
+#[test]
+fn strided_serialize_matches_compact_serialize() {
+  let mut data = vec![0.0f32; 5 * 4 * 3];
+  for (p, x) in data.iter_mut().enumerate() {
+    *x = p as f32 * 0.5;
+  }
+  let strided = Array3d::with_data_strided(data, (3, 2, 3), (5, 4));
+  let compact = strided.as_view().to_owned();
+  assert!(compact.as_view().is_contiguous());
+  let mut strided_buf = vec![];
+  strided.serialize(&mut strided_buf).unwrap();
+  let mut compact_buf = vec![];
+  compact.serialize(&mut compact_buf).unwrap();
+  assert_eq!(strided_buf, compact_buf);
+  // An empty strided array writes only the header.
+  let empty = Array3d::with_data_strided(vec![0.0f32; 5 * 4 * 3], (0, 2, 3), (5, 4));
+  let mut buf = vec![];
+  empty.serialize(&mut buf).unwrap();
+  assert_eq!(buf.len(), 8 + 3 * 8);
+}
+
+#[test]
+fn to_f32_normalized_known_values() {
+  let arr = Array3d::with_data(vec![0u8, 51, 255], (3, 1, 1));
+  let normalized = arr.to_f32_normalized(0.5, 0.25);
+  let expected = [-2.0f32, -1.2, 2.0];
+  for (&x, &y) in normalized.as_slice().iter().zip(expected.iter()) {
+    assert!((x - y).abs() < 1.0e-6);
+  }
+}
+
+#[test]
+fn stream_reader_reads_mixed_arrays() {
+  let arr = Array3d::<f32>::iota((2, 3, 1));
+  let bits = Array3d::with_data(vec![1u8, 0, 0, 1, 1], (5, 1, 1)).to_bit_array();
+  let mut buf = vec![];
+  arr.serialize(&mut buf).unwrap();
+  bits.serialize(&mut buf).unwrap();
+  let mut reader = StreamArrayReader::new(&buf[..]);
+  match reader.next_array() {
+    Ok(Some(AnyArray3d::F32(x))) => assert!(x == arr),
+    _ => panic!(),
+  }
+  match reader.next_array() {
+    Ok(Some(AnyArray3d::Bit(x))) => {
+      assert_eq!(x.bound(), (5, 1, 1));
+      assert_eq!(x.into_bytes(1).as_slice(), &[1, 0, 0, 1, 1]);
+    }
+    _ => panic!(),
+  }
+  match reader.next_array() {
+    Ok(None) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn coord_from_offset_inverts_offset() {
+  for p in 0 .. 5 {
+    assert_eq!(<usize as Shape>::coord_from_offset(p, ()).offset(()), p);
+  }
+  let bound = (3, 4);
+  let stride = 5;
+  for idx in bound.major_iter() {
+    assert_eq!(<(usize, usize) as Shape>::coord_from_offset(idx.offset(stride), stride), idx);
+  }
+  let bound = (3, 4, 2);
+  let stride = (5, 6);
+  for idx in bound.major_iter() {
+    assert_eq!(<(usize, usize, usize) as Shape>::coord_from_offset(idx.offset(stride), stride), idx);
+  }
+}
+
+#[test]
+fn reverse_axis_inplace_matches_flip_axis() {
+  let bound = (3, 4, 5);
+  let arr = Array3d::<i32>::iota(bound);
+  for axis in 0 .. 3 {
+    let mut reversed = arr.clone();
+    reversed.reverse_axis_inplace(axis);
+    assert!(reversed == arr.flip_axis(axis));
+    for (i, j, k) in bound.major_iter() {
+      let mirror = match axis {
+        0 => (bound.0 - 1 - i, j, k),
+        1 => (i, bound.1 - 1 - j, k),
+        _ => (i, j, bound.2 - 1 - k),
+      };
+      assert_eq!(reversed.get((i, j, k)), arr.get(mirror));
+    }
+  }
+  let mut arr = Array3d::<i32>::iota((2, 3, 2));
+  arr.reverse_axis_inplace(1);
+  assert_eq!(arr.as_slice(), &[4, 5, 2, 3, 0, 1, 10, 11, 8, 9, 6, 7]);
+}
+
+#[test]
+fn try_view_mut_rejects_hi_past_bound() {
+  let mut arr: Array3d<f32> = Array3d::zeros((2, 3, 4));
+  match arr.as_view_mut().try_view_mut((0, 0, 0), (2, 4, 4)) {
+    Err(ViewError::HiExceedsBound{hi, bound}) => {
+      assert_eq!(hi, (2, 4, 4));
+      assert_eq!(bound, (2, 3, 4));
+    }
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn try_view_mut_rejects_lo_after_hi() {
+  let mut arr: Array3d<f32> = Array3d::zeros((2, 3, 4));
+  match arr.as_view_mut().try_view_mut((1, 2, 0), (2, 1, 4)) {
+    Err(ViewError::LoAfterHi{lo, hi}) => {
+      assert_eq!(lo, (1, 2, 0));
+      assert_eq!(hi, (2, 1, 4));
+    }
+    _ => panic!(),
+  }
+  assert!(arr.as_view_mut().try_view_mut((1, 1, 1), (1, 1, 1)).is_ok());
+}
+
+#[test]
+fn try_view_mut_accepts_strided_parents() {
+  let mut arr = Array3d::with_data_strided(vec![0; 5 * 4 * 2], (3, 2, 2), (5, 4));
+  arr.as_view_mut().try_view_mut((1, 0, 1), (3, 2, 2)).ok().unwrap().fill_from_fn(|_| 1);
+  for (i, j, k) in (3, 2, 2).major_iter() {
+    let expected = if i >= 1 && k == 1 { 1 } else { 0 };
+    assert_eq!(*arr.get((i, j, k)).unwrap(), expected);
+  }
+}
+
+#[test]
+fn array2d_view_mut_slices_through_the_stride() {
+  let mut arr = Array2d{data: vec![0; 4 * 3], bound: (3, 3), stride: 4};
+  {
+    let mut view = arr.as_view_mut().view_mut((1, 1), (3, 3));
+    assert_eq!(view.bound(), (2, 2));
+    let stride = view.stride();
+    for (i, j) in (2, 2).major_iter() {
+      view.as_mut_slice()[(i, j).offset(stride)] = 1 + i + 2 * j;
+    }
+  }
+  assert_eq!(arr.to_nested_vec(), vec![vec![0, 0, 0], vec![0, 1, 3], vec![0, 2, 4]]);
+  // The padding between the viewed columns stays untouched.
+  assert_eq!(arr.data[7], 0);
+}
+
+#[test]
+fn array2d_try_view_mut_reports_each_error() {
+  let mut arr: Array2d<f32> = Array2d::zeros((2, 3));
+  match arr.as_view_mut().try_view_mut((0, 0), (3, 3)) {
+    Err(ViewError::HiExceedsBound{hi, bound}) => {
+      assert_eq!(hi, (3, 3));
+      assert_eq!(bound, (2, 3));
+    }
+    _ => panic!(),
+  }
+  match arr.as_view_mut().try_view_mut((1, 2), (2, 1)) {
+    Err(ViewError::LoAfterHi{lo, hi}) => {
+      assert_eq!(lo, (1, 2));
+      assert_eq!(hi, (2, 1));
+    }
+    _ => panic!(),
+  }
+  assert_eq!(arr.as_view_mut().try_view_mut((0, 1), (2, 3)).ok().unwrap().bound(), (2, 2));
+}
+
+#[test]
+fn matmul_2x3_by_3x2() {
+  let a = Array2d::from_rows(vec![vec![1.0f32, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+  let b = Array2d::from_rows(vec![vec![7.0f32, 8.0], vec![9.0, 10.0], vec![11.0, 12.0]]);
+  let c = a.matmul(&b);
+  assert_eq!(c.to_nested_vec(), vec![vec![58.0, 64.0], vec![139.0, 154.0]]);
+}
+
+#[test]
+fn sum_kahan_keeps_small_terms() {
+  let mut data = vec![1.0f32; 10001];
+  data[0] = 1.0e8;
+  let arr = Array3d::with_data(data.clone(), (10001, 1, 1));
+  // Each 1.0 is below half an ulp of 1e8, so a plain f32 sum drops them all.
+  assert_eq!(arr.fold(0.0, |sum, x| sum + x), 1.0e8);
+  assert_eq!(arr.sum_kahan(), 100010000.0);
+  assert_eq!(arr.mean(), 100010000.0 / 10001.0);
+  let arr = Array2d::try_from(Array3d::with_data(data, (10001, 1, 1))).ok().unwrap();
+  assert_eq!(arr.sum_kahan(), 100010000.0);
+}
+
+#[test]
+fn erased_serialize_heterogeneous() {
+  let arrs: Vec<Box<dyn ErasedSerialize>> = vec![
+    Box::new(Array2d{data: vec![1u8, 2, 3, 4, 5, 6], bound: (2, 3), stride: 2}),
+    Box::new(Array3d::with_data(vec![1.0f32, 2.0], (1, 2, 1))),
+  ];
+  let mut buf = vec![];
+  let mut expected_len = 0;
+  for arr in arrs.iter() {
+    arr.serialize_erased(&mut buf).ok().unwrap();
+    expected_len += arr.serial_size_erased();
+  }
+  assert_eq!(buf.len(), expected_len);
+  let mut reader = ::std::io::Cursor::new(&buf);
+  let a2: Array2d<u8> = NdArraySerialize::deserialize(&mut reader).ok().unwrap();
+  assert!(a2 == Array2d{data: vec![1u8, 2, 3, 4, 5, 6], bound: (2, 3), stride: 2});
+  let a3: Array3d<f32> = NdArraySerialize::deserialize(&mut reader).ok().unwrap();
+  assert_eq!(a3.as_slice(), &[1.0f32, 2.0]);
+  assert_eq!(reader.position() as usize, buf.len());
+}
+
+#[test]
+fn write_elems_le_exact_bytes() {
+  let mut buf = vec![];
+  write_elems_le(&[1u8, 2, 255], &mut buf).unwrap();
+  assert_eq!(buf, vec![1, 2, 255]);
+  let mut buf = vec![];
+  write_elems_le(&[1.0f32, -2.5], &mut buf).unwrap();
+  assert_eq!(buf, vec![0x00, 0x00, 0x80, 0x3f, 0x00, 0x00, 0x20, 0xc0]);
+}
+
+#[test]
+fn strided_copy_3d_between_layouts() {
+  let bound = (3, 2, 2);
+  let least = bound.to_least_stride();
+  let padded = (4, 3);
+  let src: Vec<i32> = (0 .. 12).collect();
+
+  let mut strided = vec![-1; 4 * 3 * 2];
+  strided_copy_3d(&src, least, &mut strided, padded, bound);
+  for idx in bound.major_iter() {
+    assert_eq!(strided[idx.offset(padded)], src[idx.offset(least)]);
+  }
+  assert_eq!(strided[3], -1);
+
+  let mut contiguous = vec![0; 12];
+  strided_copy_3d(&strided, padded, &mut contiguous, least, bound);
+  assert_eq!(contiguous, src);
+
+  let other = (5, 2);
+  let mut restrided = vec![-1; 5 * 2 * 2];
+  strided_copy_3d(&strided, padded, &mut restrided, other, bound);
+  for idx in bound.major_iter() {
+    assert_eq!(restrided[idx.offset(other)], src[idx.offset(least)]);
+  }
+}
+
+#[test]
+fn view_at_follows_the_stride() {
+  let arr = Array3d::<i32>::iota((4, 3, 2));
+  let view = arr.as_view().view((1, 1, 1), (3, 3, 2));
+  assert_eq!(*view.at((0, 0, 0)), 17);
+  assert_eq!(*view.at((1, 1, 0)), 22);
+  let mut arr = arr;
+  {
+    let mut view = arr.as_view_mut().view_mut((1, 1, 1), (3, 3, 2));
+    *view.at((1, 1, 0)) = -1;
+  }
+  assert_eq!(*arr.get((2, 2, 1)).unwrap(), -1);
+}
+
+#[test]
+#[should_panic(expected = "index out of bounds")]
+fn view_at_panics_out_of_range() {
+  let arr = Array3d::<i32>::iota((4, 3, 2));
+  let view = arr.as_view().view((1, 1, 1), (3, 3, 2));
+  view.at((2, 0, 0));
+}
+
+#[test]
+fn deserialize_truncated_payload_is_truncated() {
+  let arr = Array3d::with_data((0 .. 8).map(|x| x as f32).collect(), (2, 2, 2));
+  let mut buf = vec![];
+  arr.serialize(&mut buf).unwrap();
+  buf.truncate(buf.len() - 5);
+  match <Array3d<f32> as NdArraySerialize<f32, (usize, usize, usize)>>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::Truncated{expected, got}) => {
+      assert_eq!(expected, 32);
+      assert_eq!(got, 27);
+    }
+    _ => panic!(),
+  }
+
+  let bits = Array3d::<u8>::from_elem((10, 10, 1), 1).to_bit_array();
+  let mut buf = vec![];
+  bits.serialize(&mut buf).unwrap();
+  buf.truncate(buf.len() - 3);
+  match BitArray3d::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::Truncated{expected, got}) => {
+      assert_eq!(expected, 16);
+      assert_eq!(got, 13);
+    }
+    _ => panic!(),
+  }
+
+  let nd = ArrayNd::with_data((0 .. 6).map(|x| x as u8).collect(), vec![2, 3]);
+  let mut buf = vec![];
+  nd.serialize(&mut buf).unwrap();
+  buf.truncate(buf.len() - 1);
+  match ArrayNd::<u8>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::Truncated{expected, got}) => {
+      assert_eq!(expected, 6);
+      assert_eq!(got, 5);
+    }
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn msb_and_lsb_packing_differ_but_count_the_same() {
+  let bytes = Array3d::with_data(vec![1u8, 0, 0, 1, 1, 0, 0, 0, 0, 1], (10, 1, 1));
+  let lsb = BitArray3d::from_byte_array(&bytes);
+  let msb = BitArray3d::from_byte_array_msb(&bytes);
+  assert_eq!(lsb.data[0], 0b10_0001_1001);
+  assert_eq!(msb.data[0], 0b0100_0000_1001_1000);
+  assert_eq!(lsb.count_ones(), 4);
+  assert_eq!(msb.count_ones_msb(), 4);
+  assert_eq!(msb.into_bytes_msb(1).as_slice(), bytes.as_slice());
+}
+
+#[test]
+fn add_inplace_for_f32_and_i32() {
+  let mut a = Array3d::with_data(vec![1.0f32, 2.0, 3.0, 4.0], (2, 2, 1));
+  let b = Array3d::with_data(vec![0.5f32, 0.5, -1.0, 1.0], (2, 2, 1));
+  a.add_inplace(&b);
+  assert_eq!(a.as_slice(), &[1.5, 2.5, 2.0, 5.0]);
+  let mut c = Array3d::with_data(vec![1i32, 2, 3, 4], (2, 2, 1));
+  let d = Array3d::with_data(vec![10i32, -20, 30, -40], (2, 2, 1));
+  c.add_inplace(&d);
+  assert_eq!(c.as_slice(), &[11, -18, 33, -36]);
+  c.scale_inplace(2);
+  assert_eq!(c.sum(), 2 * (11 - 18 + 33 - 36));
+}
+
+#[test]
+fn integer_div_matmul_and_scatter() {
+  let mut a = Array3d::with_data(vec![7i32, 9, -8, 1], (2, 2, 1));
+  a.div_inplace(&Array3d::with_data(vec![2, 3, 4, 1], (2, 2, 1)));
+  assert_eq!(a.as_slice(), &[3, 3, -2, 1]);
+  let x = Array2d::from_rows(vec![vec![1i32, 2], vec![3, 4]]);
+  let y = Array2d::from_rows(vec![vec![5i32, 6], vec![7, 8]]);
+  assert_eq!(x.matmul(&y).to_nested_vec(), vec![vec![19, 22], vec![43, 50]]);
+  let mut acc = Array2d::<i32>::from_elem((2, 2), 0);
+  acc.scatter_add_axis0(&[1, 1], &x);
+  assert_eq!(acc.to_nested_vec(), vec![vec![0, 0], vec![4, 6]]);
+}
+
+#[test]
+fn det_and_inverse_small_matrices() {
+  let a = Array2d::from_rows(vec![vec![4.0f32, 7.0], vec![2.0, 6.0]]);
+  assert!((a.det() - 10.0).abs() < 1.0e-5);
+  let inv = a.inverse().unwrap();
+  let expected = [vec![0.6f32, -0.7], vec![-0.2, 0.4]];
+  for (row, erow) in inv.to_nested_vec().iter().zip(expected.iter()) {
+    for (&x, &y) in row.iter().zip(erow.iter()) {
+      assert!((x - y).abs() < 1.0e-5);
+    }
+  }
+
+  let a = Array2d::from_rows(vec![vec![2.0f32, 0.0, 1.0], vec![1.0, 3.0, 2.0], vec![1.0, 1.0, 2.0]]);
+  assert!((a.det() - 6.0).abs() < 1.0e-5);
+  let product = a.matmul(&a.inverse().unwrap());
+  for (i, row) in product.to_nested_vec().iter().enumerate() {
+    for (j, &x) in row.iter().enumerate() {
+      let expected = if i == j { 1.0 } else { 0.0 };
+      assert!((x - expected).abs() < 1.0e-5);
+    }
+  }
+
+  let singular = Array2d::from_rows(vec![vec![1.0f32, 2.0], vec![2.0, 4.0]]);
+  assert_eq!(singular.det(), 0.0);
+  assert!(singular.inverse().is_none());
+}
+
+#[test]
+fn strided_array2d_serialize_round_trips() {
+  let data: Vec<f32> = (0 .. 20).map(|x| x as f32).collect();
+  let arr = Array2d::try_from(Array3d::with_data_strided(data, (3, 4, 1), (5, 4))).ok().unwrap();
+  let mut buf = vec![];
+  arr.serialize(&mut buf).unwrap();
+  assert_eq!(buf.len(), <Array2d<f32> as NdArraySerialize<f32, (usize, usize)>>::serial_size((3, 4)));
+  let back = <Array2d<f32> as NdArraySerialize<f32, (usize, usize)>>::deserialize(&mut &buf[..]).unwrap();
+  assert!(back == arr);
+  assert_eq!(back.as_slice(), &[0.0, 1.0, 2.0, 5.0, 6.0, 7.0, 10.0, 11.0, 12.0, 15.0, 16.0, 17.0]);
+}
+
+#[test]
+fn deserialize_rejects_corrupted_bound() {
+  let arr = Array3d::<f32>::iota((2, 2, 2));
+  let mut buf = vec![];
+  arr.serialize(&mut buf).unwrap();
+  // The last dim follows the 8-byte prefix and two u64 dims.
+  buf[8 + 16] = 9;
+  match <Array3d<f32> as NdArraySerialize<f32, (usize, usize, usize)>>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::Truncated{..}) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn deserialize_rejects_overflowing_bound() {
+  let mut buf = vec![];
+  write_nd_header(&mut buf, 1, &[1 << 40, 1 << 40, 1 << 40]).unwrap();
+  match <Array3d<f32> as NdArraySerialize<f32, (usize, usize, usize)>>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::TooLarge) => {}
+    _ => panic!(),
+  }
+  let mut buf = vec![];
+  write_nd_header(&mut buf, 1, &[1 << 40, 1 << 40]).unwrap();
+  match <Array2d<f32> as NdArraySerialize<f32, (usize, usize)>>::deserialize(&mut &buf[..]) {
+    Err(ArrayIoError::TooLarge) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn validate_reports_shape_errors() {
+  assert!(Array3d::<f32>::iota((2, 2, 2)).validate().is_ok());
+  let arr = unsafe { Array3d::from_raw_parts(vec![0.0f32; 7], (2, 2, 2), (2, 2)) };
+  match arr.validate() {
+    Err(ShapeError::BufferTooShort{required: 8, len: 7}) => {}
+    _ => panic!(),
+  }
+  let arr = unsafe { Array3d::from_raw_parts(vec![0.0f32; 8], (2, 2, 2), (1, 2)) };
+  match arr.validate() {
+    Err(ShapeError::StrideTooSmall) => {}
+    _ => panic!(),
+  }
+}
+
+#[test]
+fn blocks_tile_the_array() {
+  let arr = Array3d::<i32>::iota((4, 4, 4));
+  let blocks: Vec<_> = arr.blocks((2, 2, 2)).collect();
+  assert_eq!(blocks.len(), 8);
+  assert_eq!(blocks[7].bound(), (2, 2, 2));
+  assert_eq!(*blocks[7].at((0, 0, 0)), *arr.get((2, 2, 2)).unwrap());
+
+  // Blocks along the far edges are clamped.
+  let arr = Array3d::<i32>::iota((5, 4, 3));
+  let blocks: Vec<_> = arr.blocks((2, 3, 2)).collect();
+  assert_eq!(blocks.len(), 3 * 2 * 2);
+  assert_eq!(blocks.last().unwrap().bound(), (1, 1, 1));
+  assert_eq!(blocks.iter().map(|b| b.len()).sum::<usize>(), 60);
+}
+
+#[test]
+fn mean_axis_and_var_axis() {
+  // Lanes along axis 0 are [1, 2, 6] and [4, 4, 4].
+  let arr = Array3d::with_data(vec![1.0f32, 2.0, 6.0, 4.0, 4.0, 4.0], (3, 2, 1));
+  assert_eq!(arr.mean_axis(0).as_slice(), &[3.0, 4.0]);
+  let var = arr.var_axis(0);
+  assert!((var.as_slice()[0] - 14.0 / 3.0).abs() < 1.0e-6);
+  assert_eq!(var.as_slice()[1], 0.0);
+  assert_eq!(arr.mean_axis(1).as_slice(), &[2.5, 3.0, 5.0]);
+}
+
+#[test]
+fn quantize_i8_round_trip_error_is_bounded() {
+  let arr = Array3d::with_data((0 .. 24).map(|x| x as f32 * 0.37 - 4.0).collect(), (2, 3, 4));
+  let (scale, zero_point) = (0.05, 3i8);
+  let restored = arr.quantize_i8(scale, zero_point).dequantize_i8(scale, zero_point);
+  for (&x, &y) in arr.as_slice().iter().zip(restored.as_slice().iter()) {
+    assert!((x - y).abs() <= scale);
+  }
+  let clamped = Array3d::with_data(vec![100.0f32, -100.0], (2, 1, 1)).quantize_i8(0.1, 0);
+  assert_eq!(clamped.as_slice(), &[127, -128]);
+}
+
+#[test]
+fn views_over_a_stack_slice() {
+  let mut buf = [0u8; 12];
+  {
+    let mut view = view_mut_from_slice(&mut buf, (2, 3, 2));
+    *view.at((1, 2, 1)) = 7;
+  }
+  assert_eq!(buf[1 + 2 * 2 + 6], 7);
+  let view = view_from_slice(&buf, (2, 3, 2));
+  assert_eq!(*view.at((1, 2, 1)), 7);
+  assert_eq!(view.to_owned().as_slice(), &buf[..]);
+}
+
+#[test]
+fn try_view_reports_invalid_ranges() {
+  let arr = Array3d::<f32>::iota((2, 3, 4));
+  match arr.as_view().try_view((0, 0, 0), (2, 3, 5)) {
+    Err(ViewError::HiExceedsBound{..}) => {}
+    _ => panic!(),
+  }
+  match arr.as_view().try_view((1, 0, 0), (0, 3, 4)) {
+    Err(ViewError::LoAfterHi{..}) => {}
+    _ => panic!(),
+  }
+  assert_eq!(arr.as_view().try_view((1, 1, 1), (2, 3, 4)).ok().unwrap().bound(), (1, 2, 3));
+  let arr = Array2d::from_rows(vec![vec![1, 2], vec![3, 4]]);
+  assert!(arr.as_view().try_view((0, 0), (3, 1)).is_err());
+  assert!(arr.as_view().try_view((0, 0), (2, 1)).is_ok());
+  let mut arr: Array3d<f32> = Array3d::zeros((2, 3, 4));
+  assert!(arr.as_view_mut().try_view_mut((0, 0, 0), (3, 3, 4)).is_err());
+}
+
+#[test]
+#[should_panic(expected = "invalid view range")]
+fn view_panics_on_invalid_range() {
+  let arr = Array3d::<f32>::iota((2, 3, 4));
+  arr.as_view().view((0, 0, 0), (2, 4, 4));
+}
+
+#[test]
+fn empty_arrays_are_empty() {
+  assert!(Array3d::<f32>::empty().is_empty());
+  assert!(!Array3d::<f32>::zeros((1, 1, 1)).is_empty());
+  assert!(Array2d::<u8>::empty().is_empty());
+  assert!(Array1d::<u8>::empty().is_empty());
+  assert!(BitArray3d::empty().is_empty());
+  let arr = Array3d::from_elem((2, 2, 2), 1.0f32);
+  assert!(!arr.as_view().is_empty());
+  assert!(arr.as_view().view((1, 0, 0), (1, 2, 2)).is_empty());
+}
+
+#[test]
+fn compat32_round_trips() {
+  let arr = Array3d::<f32>::iota((2, 3, 4));
+  let mut buf = vec![];
+  arr.serialize_compat32(&mut buf).unwrap();
+  assert_eq!(buf.len(), 8 + 3 * 4 + 24 * 4);
+  assert_eq!(buf[2], 1);
+  let back = <Array3d<f32> as NdArraySerialize<f32, (usize, usize, usize)>>::deserialize(&mut &buf[..]).unwrap();
+  assert!(back == arr);
+  let arr = Array2d::from_rows(vec![vec![1u8, 2], vec![3, 4]]);
+  let mut buf = vec![];
+  arr.serialize_compat32(&mut buf).unwrap();
+  assert!(<Array2d<u8> as NdArraySerialize<u8, (usize, usize)>>::deserialize(&mut &buf[..]).unwrap() == arr);
+}
+
+#[test]
+fn compat32_rejects_large_dims() {
+  let mut buf = vec![];
+  match write_nd_header_compat32(&mut buf, 0, &[1, 1usize << 33]) {
+    Err(ArrayIoError::TooLarge) => {}
+    _ => panic!(),
+  }
+  assert!(buf.is_empty());
+}
+
+#[test]
+fn compat32_empty_strided_writes_only_header() {
+  // An empty strided array need not back any of its lanes.
+  let arr = Array3d::with_data_strided(Vec::<f32>::new(), (0, 2, 2), (4, 3));
+  let mut buf = vec![];
+  arr.serialize_compat32(&mut buf).unwrap();
+  assert_eq!(buf.len(), 8 + 3 * 4);
+  let back = <Array3d<f32> as NdArraySerialize<f32, (usize, usize, usize)>>::deserialize(&mut &buf[..]).unwrap();
+  assert_eq!(back.bound(), (0, 2, 2));
+}
+
+#[test]
+fn apply_along_axis_matches_reverse_axis_inplace() {
+  let mut arr = Array3d::<i32>::iota((3, 4, 2));
+  arr.apply_along_axis(1, |lane| lane.reverse());
+  let mut expected = Array3d::<i32>::iota((3, 4, 2));
+  expected.reverse_axis_inplace(1);
+  assert!(arr == expected);
+}
+
+#[test]
+fn nan_and_inf_checks_skip_padding() {
+  // Positions 3 and 4 are stride padding, so only the NaN at 6 counts.
+  let mut data = vec![0.0f32; 20];
+  data[3] = f32::NAN;
+  data[4] = f32::NAN;
+  data[6] = f32::NAN;
+  data[7] = f32::NEG_INFINITY;
+  let arr = Array3d::with_data_strided(data, (3, 4, 1), (5, 4));
+  assert!(arr.has_nan());
+  assert!(arr.has_inf());
+  assert_eq!(arr.count_nan(), 1);
+  let arr = Array2d::from_rows(vec![vec![1.0f32, f32::NAN], vec![f32::INFINITY, 2.0]]);
+  assert!(arr.has_nan());
+  assert!(arr.has_inf());
+  assert_eq!(arr.count_nan(), 1);
+  let arr = Array3d::from_elem((2, 2, 2), 1.0f32);
+  assert!(!arr.has_nan());
+  assert!(!arr.has_inf());
+}
+
+#[test]
+fn copy_into_slice_matches_naive_loop() {
+  let arr = Array3d::with_data((0 .. 16 * 16 * 16).collect::<Vec<u32>>(), (16, 16, 16));
+  let view = arr.as_view().view((3, 5, 2), (11, 13, 10));
+  let mut fast = vec![0; 512];
+  view.copy_into_slice(&mut fast);
+  let mut naive = vec![];
+  for idx in (8, 8, 8).major_iter() {
+    naive.push(*view.at(idx));
+  }
+  assert_eq!(fast, naive);
+
+  let arr = Array2d::from_rows((0 .. 10).map(|i| (0 .. 7).map(|j| 7 * i + j).collect()).collect::<Vec<Vec<u32>>>());
+  let view = arr.as_view().view((2, 1), (9, 6));
+  let mut fast = vec![0; 35];
+  view.copy_into_slice(&mut fast);
+  let mut naive = vec![];
+  for idx in (7, 5).major_iter() {
+    naive.push(*view.at(idx));
+  }
+  assert_eq!(fast, naive);
+}
+
+#[test]
+fn any_array_deserializes_bit_arrays() {
+  let bytes = Array3d::with_data((0 .. 70).map(|x| (x % 3 == 0) as u8).collect(), (7, 5, 2));
+  let bits = bytes.to_bit_array();
+  let mut buf = vec![];
+  bits.serialize(&mut buf).unwrap();
+  let any = AnyArray3d::deserialize(&mut &buf[..]).unwrap();
+  assert_eq!(any.data_type_id(), 255);
+  assert_eq!(any.bound(), (7, 5, 2));
+  let back = any.into_bit().unwrap();
+  assert_eq!(back.into_bytes(1).as_slice(), bytes.as_slice());
+}